@@ -3,6 +3,18 @@ use core::cmp::{max, min};
 use alloc::alloc::Layout;
 
 
+// Byte counts of a `BuddyAllocator`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuddyStats {
+    // requested by the callers
+    pub user: usize,
+    // handed out, requests rounded up to whole blocks
+    pub allocated: usize,
+    pub total: usize,
+    // the largest free block, less than `total - allocated` when fragmented
+    pub largest_free: usize,
+}
+
 pub struct BuddyAllocator {
     free_list: [LinkedList; 32],
 
@@ -13,14 +25,22 @@ pub struct BuddyAllocator {
 }
 
 impl BuddyAllocator {
-    // Create a new heap
+    /// Create a new heap
+    ///
+    /// # Safety
+    ///
+    /// [start, end) must be unused memory that stays valid while the heap is in use.
     pub unsafe fn new(unit:usize, start: usize, end: usize) -> Self {
         let mut new_allocator = Self::empty(unit);
         new_allocator.add_to_heap(start, end);
         new_allocator
     }
 
-    // Add a range of memory [start, start+size) to the heap
+    /// Add a range of memory [start, start+size) to the heap
+    ///
+    /// # Safety
+    ///
+    /// [start, start+size) must be unused memory that stays valid while the heap is in use.
     pub unsafe fn init(&mut self, start: usize, size: usize) {
         self.add_to_heap(start, start + size);
     }
@@ -36,20 +56,47 @@ impl BuddyAllocator {
         }
     }
 
-    // Add a range of memory [start, end) to the heap
+    // Bytes not handed out, in blocks of any size
+    pub fn free(&self) -> usize {
+        self.total - self.allocated
+    }
+
+    pub fn stats(&self) -> BuddyStats {
+        BuddyStats {
+            user: self.user,
+            allocated: self.allocated,
+            total: self.total,
+            largest_free: self
+                .free_list
+                .iter()
+                .rposition(|list| !list.is_empty())
+                .map_or(0, |level| 1 << level),
+        }
+    }
+
+    /// Add a range of memory [start, end) to the heap
+    ///
+    /// # Safety
+    ///
+    /// [start, end) must be unused memory that stays valid while the heap is in use.
     pub unsafe fn add_to_heap(&mut self, mut start: usize, mut end: usize) {
         start = (start + self.unit - 1) & (!self.unit + 1);
         end &= !self.unit + 1;
         self.total += end - start;
 
+        // blocks are aligned to their size, so that a buddy is found by flipping one bit
         while start < end {
-            let level = (end - start).trailing_zeros() as usize;
+            let level = min(
+                min(start.trailing_zeros(), usize::BITS - 1 - (end - start).leading_zeros()) as usize,
+                self.free_list.len() - 1,
+            );
             self.free_list[level].push(start as *mut usize);
             start += 1 << level;
         }
     }
 
-    // Alloc a range of memory from the heap satisfying `layout` requirements
+    // Alloc a range of memory from the heap satisfying `layout` requirements,
+    // null if there is no block large enough
     pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
         let size = max(
             layout.size().next_power_of_two(),
@@ -68,7 +115,7 @@ impl BuddyAllocator {
                 return result as *mut u8;
             }
         }
-        panic!("[buddy allocator] out of memory when alloc size {}", size);
+        core::ptr::null_mut()
     }
 
     // Dealloc a range of memory from the heap
@@ -79,6 +126,8 @@ impl BuddyAllocator {
         );
         let level = size.trailing_zeros() as usize;
         self.merge(level, ptr);
+        self.user -= layout.size();
+        self.allocated -= size;
     }
 
     fn split(&mut self, start: usize, end: usize) {
@@ -97,22 +146,259 @@ impl BuddyAllocator {
 
     fn merge(&mut self, start: usize, ptr: *mut u8) {
         let mut curr = ptr as usize;
-        for i in start..self.free_list.len() {
-            let buddy = curr ^ (1 << i);
-            let target = self.free_list[i]
+        let mut level = start;
+        // blocks of the last level have no buddy to merge with
+        while level + 1 < self.free_list.len() {
+            let buddy = curr ^ (1 << level);
+            let target = self.free_list[level]
                 .iter_mut()
                 .find(|node| node.ptr() as usize == buddy);
 
-            if let Some(node) = target {
-                node.pop();
-                curr = min(curr, buddy);
-            } else {
-                unsafe {
-                    self.free_list[i].push(curr as *mut usize);
+            match target {
+                Some(node) => {
+                    node.pop();
+                    curr = min(curr, buddy);
+                    level += 1;
                 }
-                break;
+                None => break,
             }
         }
+        unsafe {
+            self.free_list[level].push(curr as *mut usize);
+        }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::alloc::{alloc, dealloc};
+    use alloc::vec::Vec;
+
+    const UNIT: usize = 16;
+
+    // xorshift64, randomized runs without pulling in a crate
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    // Memory from the host allocator for a heap to manage
+    struct Arena {
+        base: *mut u8,
+        layout: Layout,
+    }
+
+    impl Arena {
+        fn new(size: usize, align: usize) -> Self {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let base = unsafe { alloc(layout) };
+            assert!(!base.is_null());
+            Self { base, layout }
+        }
+
+        fn range(&self) -> (usize, usize) {
+            (self.base as usize, self.base as usize + self.layout.size())
+        }
+    }
+
+    impl Drop for Arena {
+        fn drop(&mut self) {
+            unsafe { dealloc(self.base, self.layout) };
+        }
+    }
+
+    fn block_size(unit: usize, layout: &Layout) -> usize {
+        max(layout.size().next_power_of_two(), max(layout.align(), unit))
+    }
+
+    // every free block as (start, size), sorted
+    fn free_blocks(heap: &BuddyAllocator) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<_> = heap
+            .free_list
+            .iter()
+            .enumerate()
+            .flat_map(|(level, list)| list.iter().map(move |ptr| (ptr as usize, 1 << level)))
+            .collect();
+        blocks.sort();
+        blocks
+    }
+
+    // Free blocks are aligned to their size, do not overlap and add up with the
+    // allocated bytes to the total
+    fn check_free_lists(heap: &BuddyAllocator) {
+        let blocks = free_blocks(heap);
+        for window in blocks.windows(2) {
+            assert!(window[0].0 + window[0].1 <= window[1].0, "free blocks {:x?} overlap", window);
+        }
+        for &(start, size) in &blocks {
+            assert_eq!(start % size, 0, "free block {:#x} of size {:#x} is not aligned", start, size);
+        }
+        let free: usize = blocks.iter().map(|&(_, size)| size).sum();
+        assert_eq!(free + heap.allocated, heap.total);
+        assert_eq!(heap.free(), free);
+    }
+
+    #[test]
+    fn accounting() {
+        let arena = Arena::new(1 << 16, 1 << 16);
+        let (start, end) = arena.range();
+        let mut heap = unsafe { BuddyAllocator::new(UNIT, start, end) };
+        assert_eq!(heap.total, 1 << 16);
+        let a = Layout::from_size_align(100, 8).unwrap();
+        let b = Layout::from_size_align(1, 1).unwrap();
+        let pa = heap.alloc(a);
+        let pb = heap.alloc(b);
+        assert_eq!(heap.user, 101);
+        assert_eq!(heap.allocated, 128 + UNIT);
+        check_free_lists(&heap);
+        heap.dealloc(pa, a);
+        assert_eq!(heap.user, 1);
+        assert_eq!(heap.allocated, UNIT);
+        heap.dealloc(pb, b);
+        assert_eq!((heap.user, heap.allocated), (0, 0));
+        check_free_lists(&heap);
+        assert_eq!(
+            heap.stats(),
+            BuddyStats { user: 0, allocated: 0, total: 1 << 16, largest_free: 1 << 16 }
+        );
+    }
+
+    #[test]
+    fn unaligned_region_gives_aligned_blocks() {
+        let arena = Arena::new(1 << 16, 1 << 16);
+        let (start, end) = arena.range();
+        // starts one unit into the arena, so no block may start there with a larger size
+        let mut heap = unsafe { BuddyAllocator::new(UNIT, start + UNIT, end - 3 * UNIT) };
+        assert_eq!(heap.total, (1 << 16) - 4 * UNIT);
+        check_free_lists(&heap);
+        let layout = Layout::from_size_align(4096, 4096).unwrap();
+        let mut ptrs = Vec::new();
+        loop {
+            let ptr = heap.alloc(layout);
+            if ptr.is_null() {
+                break;
+            }
+            assert_eq!(ptr as usize % 4096, 0);
+            ptrs.push(ptr);
+        }
+        // the first and last 4K pages are cut short
+        assert_eq!(ptrs.len(), (1 << 16) / 4096 - 2);
+        for ptr in ptrs {
+            heap.dealloc(ptr, layout);
+        }
+        check_free_lists(&heap);
+    }
+
+    #[test]
+    fn out_of_memory_is_null() {
+        let arena = Arena::new(1 << 12, 1 << 12);
+        let (start, end) = arena.range();
+        let mut heap = unsafe { BuddyAllocator::new(UNIT, start, end) };
+        assert!(heap.alloc(Layout::from_size_align(1 << 13, 8).unwrap()).is_null());
+        let whole = Layout::from_size_align(1 << 12, 8).unwrap();
+        let ptr = heap.alloc(whole);
+        assert_eq!(ptr as usize, start);
+        assert!(heap.alloc(Layout::from_size_align(1, 1).unwrap()).is_null());
+        heap.dealloc(ptr, whole);
+        assert_eq!(heap.user, 0);
+    }
+
+    // Free everything in some order, the heap is back to a single block
+    fn merge_fully_coalesces(mut order: impl FnMut(&mut Vec<(*mut u8, Layout)>)) {
+        let size = 1 << 16;
+        let arena = Arena::new(size, size);
+        let (start, end) = arena.range();
+        let mut heap = unsafe { BuddyAllocator::new(UNIT, start, end) };
+        let layout = Layout::from_size_align(UNIT, 1).unwrap();
+        let mut ptrs: Vec<_> = (0..size / UNIT).map(|_| (heap.alloc(layout), layout)).collect();
+        assert!(ptrs.iter().all(|(ptr, _)| !ptr.is_null()));
+        order(&mut ptrs);
+        for (ptr, layout) in ptrs {
+            heap.dealloc(ptr, layout);
+        }
+        assert_eq!(free_blocks(&heap), [(start, size)]);
+    }
+
+    #[test]
+    fn merge_in_address_order() {
+        merge_fully_coalesces(|_| {});
+    }
+
+    #[test]
+    fn merge_in_reverse_order() {
+        merge_fully_coalesces(|ptrs| ptrs.reverse());
+    }
+
+    #[test]
+    fn merge_in_random_order() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        merge_fully_coalesces(|ptrs| {
+            for i in (1..ptrs.len()).rev() {
+                ptrs.swap(i, rng.below(i + 1));
+            }
+        });
+    }
+
+    // Random alloc/dealloc sequences: allocations are aligned, inside the heap and
+    // disjoint, their contents survive, and the byte counts stay consistent
+    #[test]
+    fn random_alloc_dealloc() {
+        for seed in 1..=20u64 {
+            let size = 1 << 20;
+            let arena = Arena::new(size, size);
+            let (start, end) = arena.range();
+            let mut heap = unsafe { BuddyAllocator::new(UNIT, start, end) };
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            // (address, layout, fill byte)
+            let mut live: Vec<(usize, Layout, u8)> = Vec::new();
+            for step in 0..2000 {
+                if live.is_empty() || rng.below(5) < 3 {
+                    // sizes spread over the orders of magnitude
+                    let max_size = 1 << (4 + rng.below(12));
+                    let layout = Layout::from_size_align(1 + rng.below(max_size), 1 << rng.below(13)).unwrap();
+                    let ptr = heap.alloc(layout) as usize;
+                    let block = block_size(UNIT, &layout);
+                    if ptr == 0 {
+                        // only if no free block is large enough
+                        assert!(free_blocks(&heap).iter().all(|&(_, size)| size < block));
+                        continue;
+                    }
+                    assert_eq!(ptr % layout.align(), 0);
+                    assert!(start <= ptr && ptr + layout.size() <= end);
+                    for &(other, other_layout, _) in &live {
+                        let other_block = block_size(UNIT, &other_layout);
+                        assert!(ptr + block <= other || other + other_block <= ptr, "step {}: {:#x} overlaps {:#x}", step, ptr, other);
+                    }
+                    let fill = rng.next() as u8;
+                    unsafe { core::ptr::write_bytes(ptr as *mut u8, fill, layout.size()) };
+                    live.push((ptr, layout, fill));
+                } else {
+                    let (ptr, layout, fill) = live.swap_remove(rng.below(live.len()));
+                    let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, layout.size()) };
+                    assert!(bytes.iter().all(|&b| b == fill), "step {}: {:#x} was overwritten", step, ptr);
+                    heap.dealloc(ptr as *mut u8, layout);
+                }
+                assert_eq!(heap.user, live.iter().map(|(_, layout, _)| layout.size()).sum::<usize>());
+                assert_eq!(heap.allocated, live.iter().map(|(_, layout, _)| block_size(UNIT, layout)).sum::<usize>());
+                assert_eq!(heap.total, size);
+            }
+            check_free_lists(&heap);
+            for (ptr, layout, _) in live.drain(..) {
+                heap.dealloc(ptr as *mut u8, layout);
+            }
+            assert_eq!(free_blocks(&heap), [(start, size)]);
+        }
+    }
+}
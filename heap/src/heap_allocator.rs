@@ -14,6 +14,9 @@ impl LockedBuddyHeap {
         }
     }
 
+    /// # Safety
+    ///
+    /// See `BuddyAllocator::add_to_heap`.
     pub unsafe fn add_to_heap(&self, start: usize, end: usize) {
         self.allocator.inner.borrow_mut().add_to_heap(start, end);
     }
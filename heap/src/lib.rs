@@ -1,8 +1,9 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
 pub mod buddy_allocator;
 pub mod linked_list;
 pub mod heap_allocator;
+pub mod slub_allocator;
 mod temp_mut;
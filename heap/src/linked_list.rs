@@ -1,8 +1,16 @@
+use core::marker::PhantomData;
+
 #[derive(Copy, Clone)]
 pub struct LinkedList {
     head: *mut usize,
 }
 
+impl Default for LinkedList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LinkedList {
     // Create a new LinkedList
     pub const fn new() -> LinkedList {
@@ -16,7 +24,11 @@ impl LinkedList {
         self.head.is_null()
     }
 
-    // Push `node` to the front of the list
+    /// Push `node` to the front of the list
+    ///
+    /// # Safety
+    ///
+    /// `node` must be valid for writing a `usize` and stay unused while on the list.
     pub unsafe fn push(&mut self, node: *mut usize) {
         *node = self.head as usize;
         self.head = node;
@@ -33,18 +45,18 @@ impl LinkedList {
         }
     }
 
-    pub fn iter(&self) -> LinkedListIter {
+    pub fn iter(&self) -> LinkedListIter<'_> {
         LinkedListIter {
             curr: self.head,
-            linked_list: self,
+            linked_list: PhantomData,
         }
     }
 
-    pub fn iter_mut(&mut self) -> LinkedListMutIter {
+    pub fn iter_mut(&mut self) -> LinkedListMutIter<'_> {
         LinkedListMutIter {
             prev: &mut self.head as *mut *mut usize as *mut usize,
             curr: self.head,
-            linked_list: self,
+            linked_list: PhantomData,
         }
     }
 }
@@ -76,7 +88,7 @@ impl ListNode {
 
 pub struct LinkedListIter<'a> {
     curr: *mut usize,
-    linked_list: &'a LinkedList,
+    linked_list: PhantomData<&'a LinkedList>,
 }
 
 impl<'a> Iterator for LinkedListIter<'a> {
@@ -96,7 +108,7 @@ impl<'a> Iterator for LinkedListIter<'a> {
 pub struct LinkedListMutIter<'a> {
     prev: *mut usize,
     curr: *mut usize,
-    linked_list: &'a mut LinkedList,
+    linked_list: PhantomData<&'a mut LinkedList>,
 }
 
 impl<'a> Iterator for LinkedListMutIter<'a> {
@@ -116,3 +128,49 @@ impl<'a> Iterator for LinkedListMutIter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn push_pop_is_lifo() {
+        let mut nodes = [0usize; 4];
+        let mut list = LinkedList::new();
+        assert!(list.is_empty());
+        for node in nodes.iter_mut() {
+            unsafe { list.push(node) };
+        }
+        let popped: Vec<_> = core::iter::from_fn(|| list.pop()).collect();
+        let expected: Vec<_> = nodes.iter_mut().rev().map(|node| node as *mut usize).collect();
+        assert_eq!(popped, expected);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn iter_visits_from_the_front() {
+        let mut nodes = [0usize; 3];
+        let mut list = LinkedList::new();
+        for node in nodes.iter_mut() {
+            unsafe { list.push(node) };
+        }
+        let seen: Vec<_> = list.iter().collect();
+        let expected: Vec<_> = nodes.iter_mut().rev().map(|node| node as *mut usize).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn pop_from_the_middle() {
+        let mut nodes = [0usize; 3];
+        let mut list = LinkedList::new();
+        for node in nodes.iter_mut() {
+            unsafe { list.push(node) };
+        }
+        let middle = &mut nodes[1] as *mut usize;
+        let node = list.iter_mut().find(|node| node.ptr() == middle).unwrap();
+        assert_eq!(node.pop(), middle);
+        let rest: Vec<_> = list.iter().collect();
+        assert_eq!(rest, [&mut nodes[2] as *mut usize, &mut nodes[0] as *mut usize]);
+    }
+}
@@ -0,0 +1,579 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use crate::linked_list::LinkedList;
+
+// SLUB: objects smaller than half a page are cut from page-sized slabs, with one
+// cache per power-of-two size. A cache keeps its slabs on three lists: partial
+// slabs are allocated from first, full ones have no free object and empty ones
+// are kept around (a few of them) instead of going straight back to the pages.
+
+pub const SLAB_SIZE: usize = 4096;
+// a free object holds the link of the slab's free list
+pub const MIN_OBJECT_SIZE: usize = 8;
+pub const MAX_OBJECT_SIZE: usize = SLAB_SIZE / 2;
+pub const CACHE_COUNT: usize = (MAX_OBJECT_SIZE / MIN_OBJECT_SIZE).trailing_zeros() as usize + 1;
+// empty slabs a cache holds on to before giving pages back
+const MAX_EMPTY_SLABS: usize = 1;
+
+// free objects are filled with this byte past their link when poisoning
+pub const POISON_FREE: u8 = 0x6b;
+// and the redzone after each object with this one
+pub const REDZONE_BYTE: u8 = 0xbb;
+
+// Debug checks, both cost time on every allocation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlubDebug {
+    // fill freed objects and check they are untouched when handed out again
+    pub poison: bool,
+    // put a guard area after every object and check it on free
+    pub redzone: bool,
+}
+
+impl SlubDebug {
+    pub const NONE: Self = Self { poison: false, redzone: false };
+    pub const ALL: Self = Self { poison: true, redzone: true };
+
+    fn any(&self) -> bool {
+        self.poison || self.redzone
+    }
+}
+
+// Usage of one size class
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlabCacheStats {
+    pub object_size: usize,
+    pub objects_in_use: usize,
+    // objects in all slabs of the cache, free or not
+    pub objects_total: usize,
+    pub partial_slabs: usize,
+    pub full_slabs: usize,
+    pub empty_slabs: usize,
+}
+
+/// Where slabs come from: page-sized, page-aligned blocks and the `Slab` of each.
+///
+/// # Safety
+///
+/// Pages must be valid for reads and writes of `SLAB_SIZE` bytes until given back,
+/// and `slab_of` must return the same `Slab` for every address in a page, and `None`
+/// for addresses outside the pages handed out.
+pub unsafe trait SlabProvider {
+    // A new page, null if there is none
+    fn alloc_page(&mut self) -> *mut u8;
+    /// # Safety
+    ///
+    /// `page` came from `alloc_page` and is not used any more.
+    unsafe fn dealloc_page(&mut self, page: *mut u8);
+    // The metadata of the page holding `addr`
+    fn slab_of(&mut self, addr: usize) -> Option<NonNull<Slab>>;
+}
+
+// Metadata of a slab, kept outside of the page by the provider
+pub struct Slab {
+    page: usize,
+    // index of the cache the slab belongs to
+    cache: usize,
+    free: LinkedList,
+    inuse: usize,
+    prev: Option<NonNull<Slab>>,
+    next: Option<NonNull<Slab>>,
+}
+
+impl Slab {
+    pub const fn empty() -> Self {
+        Self {
+            page: 0,
+            cache: 0,
+            free: LinkedList::new(),
+            inuse: 0,
+            prev: None,
+            next: None,
+        }
+    }
+}
+
+// An intrusive doubly linked list of slabs
+#[derive(Clone, Copy)]
+struct SlabList {
+    head: Option<NonNull<Slab>>,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    unsafe fn push(&mut self, mut slab: NonNull<Slab>) {
+        let node = slab.as_mut();
+        node.prev = None;
+        node.next = self.head;
+        if let Some(mut head) = self.head {
+            head.as_mut().prev = Some(slab);
+        }
+        self.head = Some(slab);
+        self.len += 1;
+    }
+
+    // `slab` must be on this list
+    unsafe fn remove(&mut self, mut slab: NonNull<Slab>) {
+        let node = slab.as_mut();
+        match node.prev {
+            Some(mut prev) => prev.as_mut().next = node.next,
+            None => self.head = node.next,
+        }
+        if let Some(mut next) = node.next {
+            next.as_mut().prev = node.prev;
+        }
+        node.prev = None;
+        node.next = None;
+        self.len -= 1;
+    }
+
+    fn pop(&mut self) -> Option<NonNull<Slab>> {
+        let head = self.head?;
+        unsafe { self.remove(head) };
+        Some(head)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct KmemCache {
+    size: usize,
+    // distance between objects, twice the size with redzones
+    stride: usize,
+    // objects handed out
+    inuse: usize,
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+}
+
+impl KmemCache {
+    const fn new(size: usize, debug: SlubDebug) -> Self {
+        Self {
+            size,
+            stride: if debug.redzone { size * 2 } else { size },
+            inuse: 0,
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+        }
+    }
+
+    fn objects(&self) -> usize {
+        SLAB_SIZE / self.stride
+    }
+
+    fn stats(&self) -> SlabCacheStats {
+        let slabs = self.partial.len + self.full.len + self.empty.len;
+        SlabCacheStats {
+            object_size: self.size,
+            objects_in_use: self.inuse,
+            objects_total: slabs * self.objects(),
+            partial_slabs: self.partial.len,
+            full_slabs: self.full.len,
+            empty_slabs: self.empty.len,
+        }
+    }
+}
+
+pub struct SlubAllocator<P: SlabProvider> {
+    caches: [KmemCache; CACHE_COUNT],
+    provider: P,
+    debug: SlubDebug,
+}
+
+impl<P: SlabProvider> SlubAllocator<P> {
+    pub const fn new(provider: P, debug: SlubDebug) -> Self {
+        let mut caches = [KmemCache::new(0, debug); CACHE_COUNT];
+        let mut i = 0;
+        while i < CACHE_COUNT {
+            caches[i] = KmemCache::new(MIN_OBJECT_SIZE << i, debug);
+            i += 1;
+        }
+        Self { caches, provider, debug }
+    }
+
+    pub fn provider(&mut self) -> &mut P {
+        &mut self.provider
+    }
+
+    // Usage of every size class, smallest first
+    pub fn stats(&self) -> [SlabCacheStats; CACHE_COUNT] {
+        core::array::from_fn(|i| self.caches[i].stats())
+    }
+
+    // Whether `layout` is small enough to be served by a cache
+    pub fn serves(layout: &Layout) -> bool {
+        Self::cache_index(layout).is_some()
+    }
+
+    fn cache_index(layout: &Layout) -> Option<usize> {
+        // objects are aligned to their size
+        let size = layout.size().max(layout.align()).max(MIN_OBJECT_SIZE).next_power_of_two();
+        if size > MAX_OBJECT_SIZE {
+            return None;
+        }
+        Some((size / MIN_OBJECT_SIZE).trailing_zeros() as usize)
+    }
+
+    // An object for `layout`, null if no page is left for a new slab
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let index = Self::cache_index(&layout).expect("[slub] layout too large for a slab");
+        let slab = match self.caches[index].partial.head {
+            Some(slab) => slab,
+            None => {
+                let slab = match self.caches[index].empty.pop() {
+                    Some(slab) => slab,
+                    None => match self.new_slab(index) {
+                        Some(slab) => slab,
+                        None => return core::ptr::null_mut(),
+                    },
+                };
+                unsafe { self.caches[index].partial.push(slab) };
+                slab
+            }
+        };
+        let cache = &mut self.caches[index];
+        let (object, now_full) = unsafe {
+            let node = &mut *slab.as_ptr();
+            let object = node.free.pop().expect("[slub] partial slab without free objects") as usize;
+            node.inuse += 1;
+            (object, node.free.is_empty())
+        };
+        cache.inuse += 1;
+        if now_full {
+            unsafe {
+                cache.partial.remove(slab);
+                cache.full.push(slab);
+            }
+        }
+        if self.debug.poison {
+            // the link was just read, the rest has to be as the free left it
+            let bytes = unsafe { core::slice::from_raw_parts(object as *const u8, cache.size) };
+            if bytes[MIN_OBJECT_SIZE..].iter().any(|&b| b != POISON_FREE) {
+                panic!("[slub] object {:#x} of size {} written after free", object, cache.size);
+            }
+        }
+        object as *mut u8
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` was returned by `alloc` with the same `layout` and is not used any more.
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let index = Self::cache_index(&layout).expect("[slub] layout too large for a slab");
+        let addr = ptr as usize;
+        let slab = self
+            .provider
+            .slab_of(addr)
+            .unwrap_or_else(|| panic!("[slub] free of {:#x}, which is not from a slab", addr));
+        let cache = &mut self.caches[index];
+        let node = &mut *slab.as_ptr();
+        if node.inuse == 0 || node.page != addr & !(SLAB_SIZE - 1) {
+            panic!("[slub] free of {:#x}, which is not allocated", addr);
+        }
+        if node.cache != index {
+            panic!("[slub] free of {:#x} with size {}, allocated with size {}", addr, cache.size, MIN_OBJECT_SIZE << node.cache);
+        }
+        let offset = addr - node.page;
+        if !offset.is_multiple_of(cache.stride) || offset / cache.stride >= cache.objects() {
+            panic!("[slub] free of {:#x}, which is not the start of an object", addr);
+        }
+        if self.debug.any() && node.free.iter().any(|free| free as usize == addr) {
+            panic!("[slub] double free of {:#x}", addr);
+        }
+        if self.debug.redzone {
+            let redzone = core::slice::from_raw_parts((addr + cache.size) as *const u8, cache.stride - cache.size);
+            if redzone.iter().any(|&b| b != REDZONE_BYTE) {
+                panic!("[slub] redzone after object {:#x} of size {} overwritten", addr, cache.size);
+            }
+        }
+        if self.debug.poison {
+            core::ptr::write_bytes(ptr.add(MIN_OBJECT_SIZE), POISON_FREE, cache.size - MIN_OBJECT_SIZE);
+        }
+        let was_full = node.free.is_empty();
+        node.free.push(ptr as *mut usize);
+        node.inuse -= 1;
+        cache.inuse -= 1;
+        if was_full {
+            cache.full.remove(slab);
+            cache.partial.push(slab);
+        }
+        if node.inuse == 0 {
+            cache.partial.remove(slab);
+            if cache.empty.len < MAX_EMPTY_SLABS {
+                cache.empty.push(slab);
+            } else {
+                let page = node.page;
+                *node = Slab::empty();
+                self.provider.dealloc_page(page as *mut u8);
+            }
+        }
+    }
+
+    // Take a page and cut it into free objects for cache `index`
+    fn new_slab(&mut self, index: usize) -> Option<NonNull<Slab>> {
+        let page = self.provider.alloc_page();
+        if page.is_null() {
+            return None;
+        }
+        let slab = self
+            .provider
+            .slab_of(page as usize)
+            .expect("[slub] provider gave a page without a slab");
+        let cache = &self.caches[index];
+        unsafe {
+            if self.debug.poison {
+                core::ptr::write_bytes(page, POISON_FREE, SLAB_SIZE);
+            }
+            let node = &mut *slab.as_ptr();
+            *node = Slab::empty();
+            node.page = page as usize;
+            node.cache = index;
+            // pushed in reverse, so that objects are handed out in address order
+            for i in (0..cache.objects()).rev() {
+                let object = page.add(i * cache.stride);
+                if self.debug.redzone {
+                    core::ptr::write_bytes(object.add(cache.size), REDZONE_BYTE, cache.stride - cache.size);
+                }
+                node.free.push(object as *mut usize);
+            }
+        }
+        Some(slab)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::alloc::{alloc, dealloc};
+    use alloc::vec::Vec;
+
+    const PAGES: usize = 32;
+
+    // A fixed arena of pages with their slabs, like the kernel heap regions
+    struct TestPages {
+        base: *mut u8,
+        slabs: Vec<Slab>,
+        free: Vec<usize>,
+    }
+
+    impl TestPages {
+        fn new(pages: usize) -> Self {
+            let base = unsafe { alloc(Self::layout(pages)) };
+            assert!(!base.is_null());
+            Self {
+                base,
+                slabs: (0..pages).map(|_| Slab::empty()).collect(),
+                free: (0..pages).rev().collect(),
+            }
+        }
+
+        fn layout(pages: usize) -> Layout {
+            Layout::from_size_align(pages * SLAB_SIZE, SLAB_SIZE).unwrap()
+        }
+
+        // pages handed out and not given back
+        fn in_use(&self) -> usize {
+            self.slabs.len() - self.free.len()
+        }
+    }
+
+    impl Drop for TestPages {
+        fn drop(&mut self) {
+            unsafe { dealloc(self.base, Self::layout(self.slabs.len())) };
+        }
+    }
+
+    unsafe impl SlabProvider for TestPages {
+        fn alloc_page(&mut self) -> *mut u8 {
+            match self.free.pop() {
+                Some(i) => unsafe { self.base.add(i * SLAB_SIZE) },
+                None => core::ptr::null_mut(),
+            }
+        }
+
+        unsafe fn dealloc_page(&mut self, page: *mut u8) {
+            let i = (page as usize - self.base as usize) / SLAB_SIZE;
+            assert!(!self.free.contains(&i), "page {} given back twice", i);
+            self.free.push(i);
+        }
+
+        fn slab_of(&mut self, addr: usize) -> Option<NonNull<Slab>> {
+            let offset = addr.checked_sub(self.base as usize)?;
+            self.slabs.get_mut(offset / SLAB_SIZE).map(NonNull::from)
+        }
+    }
+
+    fn slub(debug: SlubDebug) -> SlubAllocator<TestPages> {
+        SlubAllocator::new(TestPages::new(PAGES), debug)
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 1).unwrap()
+    }
+
+    #[test]
+    fn objects_are_distinct_and_aligned() {
+        for debug in [SlubDebug::NONE, SlubDebug::ALL] {
+            let mut slub = slub(debug);
+            for size in [1, 8, 24, 64, 100, 1000, MAX_OBJECT_SIZE] {
+                let layout = layout(size);
+                let ptrs: Vec<_> = (0..20).map(|_| slub.alloc(layout) as usize).collect();
+                let object_size = size.max(MIN_OBJECT_SIZE).next_power_of_two();
+                for (i, &ptr) in ptrs.iter().enumerate() {
+                    assert_eq!(ptr % object_size, 0);
+                    assert!(ptrs[..i].iter().all(|&other| other.abs_diff(ptr) >= object_size));
+                    unsafe { core::ptr::write_bytes(ptr as *mut u8, 0xcc, size) };
+                }
+                for ptr in ptrs {
+                    unsafe { slub.dealloc(ptr as *mut u8, layout) };
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alignment_larger_than_size() {
+        let mut slub = slub(SlubDebug::NONE);
+        let layout = Layout::from_size_align(8, 256).unwrap();
+        let ptr = slub.alloc(layout);
+        assert_eq!(ptr as usize % 256, 0);
+        unsafe { slub.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn slabs_move_between_lists() {
+        let mut slub = slub(SlubDebug::NONE);
+        let layout = layout(512);
+        let index = SlubAllocator::<TestPages>::cache_index(&layout).unwrap();
+        let per_slab = SLAB_SIZE / 512;
+        let ptrs: Vec<_> = (0..per_slab).map(|_| slub.alloc(layout)).collect();
+        assert_eq!(slub.caches[index].full.len, 1);
+        assert_eq!(slub.caches[index].partial.len, 0);
+        unsafe { slub.dealloc(ptrs[0], layout) };
+        assert_eq!(slub.caches[index].full.len, 0);
+        assert_eq!(slub.caches[index].partial.len, 1);
+        for &ptr in &ptrs[1..] {
+            unsafe { slub.dealloc(ptr, layout) };
+        }
+        assert_eq!(slub.caches[index].partial.len, 0);
+        assert_eq!(slub.caches[index].empty.len, 1);
+        // the empty slab is reused before taking a new page
+        let in_use = slub.provider().in_use();
+        let ptr = slub.alloc(layout);
+        assert_eq!(slub.provider().in_use(), in_use);
+        assert_eq!(
+            slub.stats()[index],
+            SlabCacheStats {
+                object_size: 512,
+                objects_in_use: 1,
+                objects_total: per_slab,
+                partial_slabs: 1,
+                full_slabs: 0,
+                empty_slabs: 0,
+            }
+        );
+        unsafe { slub.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn removing_the_head_keeps_the_rest_of_the_list() {
+        let mut slub = slub(SlubDebug::NONE);
+        let layout = layout(1024);
+        let per_slab = SLAB_SIZE / 1024;
+        let slabs = 4;
+        let ptrs: Vec<_> = (0..per_slab * slabs).map(|_| slub.alloc(layout)).collect();
+        // one free object in each slab, all of them partial
+        for slab in 0..slabs {
+            unsafe { slub.dealloc(ptrs[slab * per_slab], layout) };
+        }
+        // empty the slab at the head of the partial list
+        for &ptr in &ptrs[(slabs - 1) * per_slab + 1..] {
+            unsafe { slub.dealloc(ptr, layout) };
+        }
+        // the other partial slabs still have room, no page is needed
+        let in_use = slub.provider().in_use();
+        let again: Vec<_> = (0..slabs - 1).map(|_| slub.alloc(layout)).collect();
+        assert_eq!(slub.provider().in_use(), in_use);
+        for ptr in again {
+            unsafe { slub.dealloc(ptr, layout) };
+        }
+        for slab in 0..slabs - 1 {
+            for &ptr in &ptrs[slab * per_slab + 1..(slab + 1) * per_slab] {
+                unsafe { slub.dealloc(ptr, layout) };
+            }
+        }
+        // only the kept empty slab is left
+        assert_eq!(slub.provider().in_use(), MAX_EMPTY_SLABS);
+    }
+
+    #[test]
+    fn out_of_pages() {
+        let mut slub = slub(SlubDebug::NONE);
+        let layout = layout(MAX_OBJECT_SIZE);
+        let ptrs: Vec<_> = (0..PAGES * 2).map(|_| slub.alloc(layout)).collect();
+        assert!(ptrs.iter().all(|ptr| !ptr.is_null()));
+        assert!(slub.alloc(layout).is_null());
+        for ptr in ptrs {
+            unsafe { slub.dealloc(ptr, layout) };
+        }
+        assert!(!slub.alloc(layout).is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "written after free")]
+    fn poison_catches_use_after_free() {
+        let mut slub = slub(SlubDebug::ALL);
+        let layout = layout(64);
+        let ptr = slub.alloc(layout);
+        unsafe {
+            slub.dealloc(ptr, layout);
+            *ptr.add(32) = 1;
+        }
+        slub.alloc(layout);
+    }
+
+    #[test]
+    #[should_panic(expected = "redzone")]
+    fn redzone_catches_overflow() {
+        let mut slub = slub(SlubDebug::ALL);
+        let layout = layout(64);
+        let ptr = slub.alloc(layout);
+        unsafe {
+            *ptr.add(64) = 1;
+            slub.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free() {
+        let mut slub = slub(SlubDebug::ALL);
+        let layout = layout(64);
+        let ptr = slub.alloc(layout);
+        let _keep = slub.alloc(layout);
+        unsafe {
+            slub.dealloc(ptr, layout);
+            slub.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not from a slab")]
+    fn free_of_a_foreign_pointer() {
+        let mut slub = slub(SlubDebug::NONE);
+        let mut local = 0usize;
+        unsafe { slub.dealloc(&mut local as *mut usize as *mut u8, layout(8)) };
+    }
+
+    #[test]
+    #[should_panic(expected = "allocated with size")]
+    fn free_with_the_wrong_size() {
+        let mut slub = slub(SlubDebug::NONE);
+        let ptr = slub.alloc(layout(64));
+        unsafe { slub.dealloc(ptr, layout(128)) };
+    }
+}
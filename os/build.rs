@@ -3,11 +3,48 @@ use std::io::{Result, Write};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
-    println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-changed={}", target_path());
+    println!("cargo:rerun-if-changed={}", LINUX_APPS_DIR);
+    println!("cargo:rerun-if-env-changed=USR_MODE");
+    println!("cargo:rerun-if-env-changed=USR_TARGET");
     insert_app_data().unwrap();
 }
 
-static TARGET_PATH: &str = "../usr/target/riscv64gc-unknown-none-elf/release/";
+// user apps are taken from the target and profile they were built with,
+// `riscv64gc-unknown-none-elf` and `release` by default
+fn target_path() -> String {
+    let target = std::env::var("USR_TARGET").unwrap_or_else(|_| String::from("riscv64gc-unknown-none-elf"));
+    let mode = std::env::var("USR_MODE").unwrap_or_else(|_| String::from("release"));
+    format!("../usr/target/{}/{}/", target, mode)
+}
+
+// prebuilt Linux programs (static musl-riscv64 binaries) placed here are embedded under their
+// file names, their sources in src/ are not
+const LINUX_APPS_DIR: &str = "../usr/linux";
+
+// the dynamic loader and the shared usr_lib are embedded like apps when they were built,
+// given as (name looked up by the kernel, path)
+fn extra_images() -> Vec<(String, String)> {
+    let candidates = [
+        (String::from("ld.so"), String::from("../usr/ld/target/riscv64gc-unknown-none-elf/release/ld")),
+        (String::from("libusr_lib.so"), format!("{}libusr_lib.so", target_path())),
+    ];
+    let linux_apps = read_dir(LINUX_APPS_DIR)
+        .into_iter()
+        .flatten()
+        .map(|dir_entry| dir_entry.unwrap())
+        .filter(|dir_entry| dir_entry.file_type().unwrap().is_file())
+        .map(|dir_entry| {
+            let name = dir_entry.file_name().into_string().unwrap();
+            let path = format!("{}/{}", LINUX_APPS_DIR, name);
+            (name, path)
+        });
+    candidates
+        .into_iter()
+        .filter(|(_, path)| std::path::Path::new(path).exists())
+        .chain(linux_apps)
+        .collect()
+}
 
 fn insert_app_data() -> Result<()> {
     let mut f = File::create("src/link_app.s").unwrap();
@@ -17,9 +54,11 @@ fn insert_app_data() -> Result<()> {
         .map(|dir_entry| {
             let mut name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();
             name_with_ext.drain(name_with_ext.find('.').unwrap()..name_with_ext.len());
-            name_with_ext
+            let path = format!("{}{}", target_path(), name_with_ext);
+            (name_with_ext, path)
         })
         .collect();
+    apps.extend(extra_images());
     apps.sort();
 
     writeln!(
@@ -44,11 +83,11 @@ _num_app:
     .global _app_names
 _app_names:"#
     )?;
-    for app in apps.iter() {
+    for (app, _) in apps.iter() {
         writeln!(f, r#"    .string "{}""#, app)?;
     }
 
-    for (idx, app) in apps.iter().enumerate() {
+    for (idx, (app, path)) in apps.iter().enumerate() {
         println!("app_{}: {}", idx, app);
         writeln!(
             f,
@@ -58,9 +97,9 @@ _app_names:"#
     .global app_{0}_end
     .align 3
 app_{0}_start:
-    .incbin "{2}{1}"
+    .incbin "{1}"
 app_{0}_end:"#,
-            idx, app, TARGET_PATH
+            idx, path
         )?;
     }
     Ok(())
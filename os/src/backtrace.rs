@@ -0,0 +1,87 @@
+use core::arch::{asm, global_asm};
+use riscv::register::satp;
+use crate::config::{BACKTRACE_DEPTH, KSYMS_SIZE, MEMORY_END, VA_WIDTH_SV39};
+use crate::mm::address::VirtAddr;
+use crate::mm::page_table::PageTable;
+use crate::println;
+
+// Backtraces follow the frame pointer chain (the kernel is built with
+// -Cforce-frame-pointers): below each frame pointer sit the return address
+// and the caller's frame pointer. Return addresses are named from the symbol
+// table `make` writes into .ksyms after linking, "address name" lines sorted
+// by address and padded with zeros. A kernel built without it prints bare addresses.
+
+global_asm!(
+    ".section .ksyms, \"a\"",
+    ".space {size}",
+    size = const KSYMS_SIZE,
+);
+
+extern "C" {
+    fn stext();
+    fn etext();
+    fn skernel();
+    fn sksyms();
+    fn eksyms();
+}
+
+/// Whether reading the word at `addr` cannot fault
+fn readable(addr: usize) -> bool {
+    let satp = satp::read().bits();
+    // paging is still off, only RAM can be read
+    if satp >> 60 == 0 {
+        return (skernel as usize..MEMORY_END).contains(&addr);
+    }
+    // the upper bits must repeat bit 38, VirtAddr drops them
+    let high = addr >> (VA_WIDTH_SV39 - 1);
+    if high != 0 && high != usize::MAX >> (VA_WIDTH_SV39 - 1) {
+        return false;
+    }
+    PageTable::from_token(satp)
+        .translate(VirtAddr::from(addr).floor())
+        .is_some_and(|pte| pte.is_valid() && pte.readable())
+}
+
+/// The function a call returning to `ra` was made from, and the offset of `ra` into it
+fn symbolize(ra: usize) -> Option<(&'static str, usize)> {
+    let table = unsafe {
+        core::slice::from_raw_parts(sksyms as usize as *const u8, eksyms as usize - sksyms as usize)
+    };
+    let len = table.iter().position(|&byte| byte == 0).unwrap_or(table.len());
+    let table = core::str::from_utf8(&table[..len]).ok()?;
+    table
+        .lines()
+        .filter_map(|line| {
+            let (addr, name) = line.split_once(' ')?;
+            Some((usize::from_str_radix(addr, 16).ok()?, name))
+        })
+        .take_while(|&(addr, _)| addr < ra)
+        .last()
+        .map(|(addr, name)| (name, ra - addr))
+}
+
+/// Print the return addresses of the calls leading here, innermost first
+pub fn print_backtrace() {
+    let mut fp: usize;
+    unsafe { asm!("mv {}, fp", out(reg) fp) };
+    println!("[kernel] backtrace:");
+    for depth in 0..BACKTRACE_DEPTH {
+        if fp < 16 || fp % 8 != 0 || !readable(fp - 16) || !readable(fp - 8) {
+            break;
+        }
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        // the outermost frame was entered from assembly or from user mode
+        if !(stext as usize..etext as usize).contains(&ra) {
+            break;
+        }
+        match symbolize(ra) {
+            Some((name, offset)) => {
+                println!("  #{:<2} {:#x} {}+{:#x}", depth, ra, name, offset);
+            }
+            None => {
+                println!("  #{:<2} {:#x}", depth, ra);
+            }
+        }
+        fp = unsafe { *((fp - 16) as *const usize) };
+    }
+}
@@ -1,17 +1,43 @@
+use heap::slub_allocator::SlubDebug;
+use log::LevelFilter;
+
 pub const SERIAL_PORT_BASE_ADDRESS: usize = 0x1000_0000;
 pub const KERNEL_STACK_SIZE: usize = 0x2000;
-pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+// the heap starts in this static array and grows with frames once they can be allocated
+pub const KERNEL_HEAP_SIZE: usize = 0x8_0000;
 pub const KERNEL_HEAP_UNIT: usize = PAGE_SIZE;
 pub const SERIAL_PORT_MAP_SIZE: usize = 0x6;
+// virtio-mmio slot of the swap disk
+pub const VIRTIO0: usize = 0x1000_1000;
+pub const VIRTIO0_MAP_SIZE: usize = 0x1000;
 
 pub const USER_STACK_SIZE: usize = 0x2000;
+// longest path a syscall accepts, without the NUL
+pub const PATH_MAX: usize = 255;
+// room for the argument and environment strings exec puts on the user stack, with their pointers
+pub const ARG_MAX: usize = 0x1000;
 
 pub const TIMER_INTERVAL: usize = 100_0000;
+// frequency of the time counter on QEMU virt
+pub const CLOCK_FREQ: usize = 10_000_000;
 
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc; // 12
 
-pub const SLAB_NODE_NUM: usize = KERNEL_HEAP_SIZE / PAGE_SIZE;
+// the heap grows by at least 2^HEAP_GROW_ORDER frames at a time
+pub const HEAP_GROW_ORDER: usize = 6;
+pub const HEAP_MAX_REGIONS: usize = 64;
+// free heap kept for the allocations made while growing it
+pub const HEAP_RESERVE: usize = 0x1_0000;
+// poison and redzone checks of the slab allocator
+pub const SLUB_DEBUG: SlubDebug = SlubDebug::NONE;
+// room for the symbol table `make` writes into .ksyms after linking, KSYMS_SIZE in the Makefile
+pub const KSYMS_SIZE: usize = 0x8_0000;
+// log lines kept for dmesg, whatever the console shows
+pub const LOG_BUFFER_SIZE: usize = 0x2000;
+pub const LOG_BUFFER_LEVEL: LevelFilter = LevelFilter::Debug;
+// frames a backtrace prints at most
+pub const BACKTRACE_DEPTH: usize = 32;
 
 pub const PA_WIDTH_SV39: usize = 56;
 pub const VA_WIDTH_SV39: usize = 39;
@@ -19,6 +45,24 @@ pub const PPN_WIDTH_SV39: usize = PA_WIDTH_SV39 - PAGE_SIZE_BITS;
 pub const VPN_WIDTH_SV39: usize = VA_WIDTH_SV39 - PAGE_SIZE_BITS;
 
 pub const MEMORY_END: usize = 0x88000000;
+// largest block of the frame allocator, 2^18 frames is a 1 GiB page
+pub const FRAME_MAX_ORDER: usize = 18;
+
+// user segments must stay in the lower half of Sv39
+pub const USER_SPACE_END: usize = 1 << (VA_WIDTH_SV39 - 1);
+// position-independent executables are loaded above this address
+pub const ELF_DYN_BASE: usize = 0x4000_0000;
+// and dynamic loaders (PT_INTERP) above this one
+pub const ELF_INTERP_BASE: usize = 0x20_0000_0000;
+// anonymous mmaps are placed above this address, brk stays below it
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+// shared memory segments are attached above this address
+pub const SHM_BASE: usize = 0x30_0000_0000;
+// largest shared memory segment in bytes, and the pages all segments may hold together
+pub const SHMMAX: usize = 0x200_0000;
+pub const SHMALL: usize = 0x4000;
+// user mappings slide by up to this many pages
+pub const ASLR_PAGES: usize = 0x1_0000;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
\ No newline at end of file
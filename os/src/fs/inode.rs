@@ -0,0 +1,98 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use lazy_static::*;
+use crate::config::PAGE_SIZE;
+use crate::fs::File;
+use crate::mm::page_cache::page_cache_get;
+use crate::mm::user_ptr::UserSlice;
+use crate::sync::up::UPSafeCell;
+use crate::syscall::{Errno, SyscallResult};
+
+pub type InodeId = usize;
+
+/// Contents of a file. The programs linked into the kernel are the only files, they
+/// are read-only: their cached pages are mapped as text into every process running them.
+pub struct Inode {
+    pub id: InodeId,
+    image: &'static [u8],
+}
+
+impl Inode {
+    pub fn len(&self) -> usize {
+        self.image.len()
+    }
+    /// The bytes of page `page` in the file, cut at its end
+    fn page_range(page: usize, len: usize) -> (usize, usize) {
+        let start = page.saturating_mul(PAGE_SIZE).min(len);
+        (start, (start + PAGE_SIZE).min(len))
+    }
+    /// Fill `bytes` with page `page` of the file, zeros past its end
+    pub fn read_page(&self, page: usize, bytes: &mut [u8]) {
+        let (start, end) = Self::page_range(page, self.image.len());
+        bytes[..end - start].copy_from_slice(&self.image[start..end]);
+        bytes[end - start..].fill(0);
+    }
+}
+
+lazy_static! {
+    // inodes are made the first time an image is opened or mapped and stay
+    static ref INODES: UPSafeCell<BTreeMap<InodeId, Arc<Inode>>> = UPSafeCell::new(BTreeMap::new());
+}
+
+/// The inode of an image linked into the kernel, its address is the inode number
+pub fn image_inode(image: &'static [u8]) -> Arc<Inode> {
+    let id = image.as_ptr() as usize;
+    INODES
+        .exclusive_access()
+        .entry(id)
+        .or_insert_with(|| {
+            Arc::new(Inode { id, image })
+        })
+        .clone()
+}
+
+/// An inode opened for reading, read through the page cache at an offset that moves along
+pub struct InodeFile {
+    inode: Arc<Inode>,
+    offset: UPSafeCell<usize>,
+}
+
+impl InodeFile {
+    pub fn new(inode: Arc<Inode>) -> Self {
+        Self {
+            inode,
+            offset: UPSafeCell::new(0),
+        }
+    }
+}
+
+impl File for InodeFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Copy the file from the offset on into `buf`, up to the end of the file, a page at a time
+    fn read(&self, buf: UserSlice) -> SyscallResult {
+        let mut offset = self.offset.exclusive_access();
+        let mut done = 0;
+        while done < buf.len() && *offset < self.inode.len() {
+            let page = *offset / PAGE_SIZE;
+            let frame = page_cache_get(&self.inode, page)?;
+            let bytes = &frame.ppn.get_bytes_array()[*offset % PAGE_SIZE..];
+            let len = bytes.len().min(buf.len() - done).min(self.inode.len() - *offset);
+            buf.sub(done, len).copy_out(&bytes[..len])?;
+            done += len;
+            *offset += len;
+        }
+        Ok(done)
+    }
+    /// never opened for writing, see `sys_openat`
+    fn write(&self, _buf: UserSlice) -> SyscallResult {
+        Err(Errno::EBADF)
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(self.inode.clone())
+    }
+}
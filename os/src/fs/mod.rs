@@ -0,0 +1,39 @@
+// Open files of a process. There is no filesystem yet, the files are the console,
+// which fds 0, 1 and 2 start out as, and the programs linked into the kernel, which
+// openat finds under /bin.
+mod inode;
+mod stdio;
+
+use alloc::sync::Arc;
+use crate::mm::user_ptr::UserSlice;
+use crate::syscall::{Errno, SyscallResult};
+pub use inode::{image_inode, Inode, InodeFile, InodeId};
+pub use stdio::Console;
+
+pub trait File {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    /// Read into `buf`, returns the bytes read
+    fn read(&self, buf: UserSlice) -> SyscallResult;
+    /// Write from `buf`, returns the bytes written
+    fn write(&self, buf: UserSlice) -> SyscallResult;
+    /// Device request `cmd`, ENOTTY for files that are not terminals
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> SyscallResult {
+        Err(Errno::ENOTTY)
+    }
+    /// The inode behind the file, `None` for devices that can not be mapped
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+}
+
+pub type FileRef = Arc<dyn File + Send + Sync>;
+
+/// stdin, stdout and stderr of the first process, inherited by the others
+pub fn stdio() -> [FileRef; 3] {
+    [
+        Arc::new(Console::new(true, false)),
+        Arc::new(Console::new(false, true)),
+        Arc::new(Console::new(false, true)),
+    ]
+}
@@ -0,0 +1,101 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::fs::File;
+use crate::io::{stdin, stdout};
+use crate::mm::user_ptr::{UserPtr, UserSlice};
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::processor::current_user_satp;
+use crate::task::suspend_and_run_next;
+
+// termios requests, the console is always raw and only ECHO can be changed
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TCSETSW: usize = 0x5403;
+const TCSETSF: usize = 0x5404;
+const ECHO: u32 = 0o10;
+const NCCS: usize = 19;
+
+/// `struct termios` as the kernel takes it
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    iflag: u32,
+    oflag: u32,
+    cflag: u32,
+    lflag: u32,
+    line: u8,
+    cc: [u8; NCCS],
+}
+
+/// The UART, opened for reading, writing or both
+pub struct Console {
+    readable: bool,
+    writable: bool,
+    // whether input read through this file is echoed, programs drawing it
+    // themselves turn it off; a new open starts with it on
+    echo: AtomicBool,
+}
+
+impl Console {
+    pub fn new(readable: bool, writable: bool) -> Self {
+        Self {
+            readable,
+            writable,
+            echo: AtomicBool::new(true),
+        }
+    }
+}
+
+impl File for Console {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// One character per call, waiting for it like a terminal in raw mode. The UART
+    /// is polled and other tasks run until a character arrives, traps are off in here.
+    fn read(&self, buf: UserSlice) -> SyscallResult {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // check the buffer before taking the character from the UART
+        buf.write_buffers()?;
+        let c = loop {
+            match stdin().try_getchar(self.echo.load(Ordering::Relaxed)) {
+                Some(c) => break c,
+                None => suspend_and_run_next(),
+            }
+        };
+        buf.copy_out(&[c])?;
+        Ok(1)
+    }
+    fn write(&self, buf: UserSlice) -> SyscallResult {
+        // raw bytes, a character may be split across two pages
+        for buffer in buf.read_buffers()? {
+            buffer.iter().for_each(|&byte| stdout().putchar(byte));
+        }
+        Ok(buf.len())
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> SyscallResult {
+        let termios = UserPtr::new(current_user_satp(), arg as *mut Termios);
+        match cmd {
+            TCGETS => {
+                termios.write(Termios {
+                    iflag: 0,
+                    oflag: 0,
+                    cflag: 0,
+                    lflag: if self.echo.load(Ordering::Relaxed) { ECHO } else { 0 },
+                    line: 0,
+                    cc: [0; NCCS],
+                })?;
+            }
+            // nothing is buffered, so there is nothing to drain or flush
+            TCSETS | TCSETSW | TCSETSF => {
+                self.echo.store(termios.read()?.lflag & ECHO != 0, Ordering::Relaxed)
+            }
+            // TIOCGWINSZ and the others a terminal may not support
+            _ => return Err(Errno::ENOTTY),
+        }
+        Ok(0)
+    }
+}
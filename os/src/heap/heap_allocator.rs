@@ -1,42 +1,124 @@
 use core::alloc::GlobalAlloc;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use heap::buddy_allocator::BuddyAllocator;
+use heap::buddy_allocator::{BuddyAllocator, BuddyStats};
+use heap::slub_allocator::{SlabCacheStats, SlubAllocator, CACHE_COUNT};
 
-use crate::config::KERNEL_HEAP_UNIT;
+use crate::config::{HEAP_GROW_ORDER, HEAP_RESERVE, KERNEL_HEAP_UNIT, PAGE_SIZE, SLUB_DEBUG};
+use crate::mm::address::PhysAddr;
+use crate::mm::frame_allocator::{try_frame_alloc_contiguous, ContiguousFrames};
+use crate::mm::page_cache::page_cache_shrink;
+use crate::mm::swap::reclaim;
 use crate::sync::up::UPSafeCell;
 
-use super::slub_allocator::SlubAllocator;
+use super::region::{add_region, KernelPages};
 
 pub struct UPHeapAllocator {
     pub buddy_allocator: UPSafeCell<BuddyAllocator>,
-    pub slub_allocator: UPSafeCell<SlubAllocator>,
+    pub slub_allocator: UPSafeCell<SlubAllocator<KernelPages>>,
+    // set while taking frames for a new region, the allocations made on the way
+    // by swapping out user pages are served from the reserve
+    growing: AtomicBool,
 }
 
 impl UPHeapAllocator {
     pub const fn empty() -> Self {
         Self {
             buddy_allocator: UPSafeCell::new(BuddyAllocator::empty(KERNEL_HEAP_UNIT)),
-            slub_allocator: UPSafeCell::new(SlubAllocator::empty()),
+            slub_allocator: UPSafeCell::new(SlubAllocator::new(KernelPages, SLUB_DEBUG)),
+            growing: AtomicBool::new(false),
         }
     }
 
     pub unsafe fn init(&self, start: usize, end: usize) {
-        self.buddy_allocator.exclusive_access().add_to_heap(start, end);
-        self.slub_allocator.exclusive_access().init();
+        self.add_region(start, end);
     }
-}
 
-unsafe impl GlobalAlloc for UPHeapAllocator {
-    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        if layout.size() < 4096 {
+    unsafe fn add_region(&self, start: usize, end: usize) -> bool {
+        match add_region(start, end) {
+            Some(usable) => {
+                self.buddy_allocator.exclusive_access().add_to_heap(usable, end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Add a region of frames with room for a `size` byte block, false if there are no
+    // frames or the frame allocator is busy, the reserve has to do until the next call
+    fn grow(&self, size: usize) -> bool {
+        if self.growing.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        // the slab nodes take the start of the region, so only its upper half
+        // is sure to be one block
+        let pages = size.next_power_of_two().div_ceil(PAGE_SIZE) * 2;
+        let min_order = pages.trailing_zeros() as usize;
+        let grown = match take_frames(HEAP_GROW_ORDER.max(min_order), min_order) {
+            Some(frames) => {
+                let start: usize = PhysAddr::from(frames.ppn).into();
+                let end = start + (PAGE_SIZE << frames.order);
+                // the heap keeps the frames for good
+                core::mem::forget(frames);
+                unsafe { self.add_region(start, end) }
+            }
+            None => false,
+        };
+        self.growing.store(false, Ordering::Relaxed);
+        grown
+    }
+
+    pub fn stats(&self) -> (BuddyStats, [SlabCacheStats; CACHE_COUNT]) {
+        let buddy = self.buddy_allocator.exclusive_access().stats();
+        let slub = self.slub_allocator.exclusive_access().stats();
+        (buddy, slub)
+    }
+
+    fn try_alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        if SlubAllocator::<KernelPages>::serves(&layout) {
             self.slub_allocator.exclusive_access().alloc(layout)
         } else {
             self.buddy_allocator.exclusive_access().alloc(layout)
         }
     }
+}
+
+// 2^order frames for the heap, or 2^min_order if user pages have to be swapped out
+// for them the way `frame_alloc` does, short of killing a process. Evicted frames are
+// scattered, so a few more than the block are tried before giving up.
+fn take_frames(order: usize, min_order: usize) -> Option<ContiguousFrames> {
+    if let Some(frames) = try_frame_alloc_contiguous(order) {
+        return Some(frames);
+    }
+    for _ in 0..4 << min_order {
+        if let Some(frames) = try_frame_alloc_contiguous(min_order) {
+            return Some(frames);
+        }
+        if !page_cache_shrink() && !reclaim() {
+            break;
+        }
+    }
+    try_frame_alloc_contiguous(min_order)
+}
+
+unsafe impl GlobalAlloc for UPHeapAllocator {
+    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        let ptr = self.try_alloc(layout);
+        if ptr.is_null() {
+            if self.grow(layout.size()) {
+                return self.try_alloc(layout);
+            }
+            return ptr;
+        }
+        // top the heap up before it runs dry, growing it allocates as well
+        if self.buddy_allocator.exclusive_access().free() < HEAP_RESERVE {
+            self.grow(0);
+        }
+        ptr
+    }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
-        if layout.size() < 4096 {
+        if SlubAllocator::<KernelPages>::serves(&layout) {
             self.slub_allocator.exclusive_access().dealloc(ptr, layout)
         } else {
             self.buddy_allocator.exclusive_access().dealloc(ptr, layout)
@@ -1,27 +1,28 @@
 mod heap_allocator;
-mod kmem_cache;
-mod slub_allocator;
+mod region;
 
-use crate::config::{KERNEL_HEAP_SIZE, PAGE_SIZE,  SLAB_NODE_NUM};
+use heap::buddy_allocator::BuddyStats;
+use heap::slub_allocator::{SlabCacheStats, CACHE_COUNT};
+
+use crate::config::KERNEL_HEAP_SIZE;
 use crate::heap::heap_allocator::UPHeapAllocator;
-use crate::heap::kmem_cache::SlabNode;
 
+// The first region of the heap, the frame allocator needs a heap before it can grow one.
 #[link_section = ".data.heap"]
 static mut KERNEL_HEAP: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
-static mut SLABNODES: [SlabNode; SLAB_NODE_NUM] = [SlabNode::empty(); SLAB_NODE_NUM];
 
 #[global_allocator]
 static HEAP: UPHeapAllocator = UPHeapAllocator::empty();
 
+/// Usage of the buddy heap and of each slab size class
+pub fn heap_stats() -> (BuddyStats, [SlabCacheStats; CACHE_COUNT]) {
+    HEAP.stats()
+}
+
 pub fn init_heap() {
     unsafe {
-        let start = KERNEL_HEAP.as_ptr() as usize;
+        let start = core::ptr::addr_of!(KERNEL_HEAP) as usize;
         let end = start + KERNEL_HEAP_SIZE;
         HEAP.init(start, end);
-        for (i, slab) in SLABNODES.iter_mut().enumerate() {
-            slab.phy_addr = start + i * PAGE_SIZE;
-        }
     }
 }
-
-
@@ -0,0 +1,70 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use heap::slub_allocator::{Slab, SlabProvider, SLAB_SIZE};
+
+use crate::config::{HEAP_MAX_REGIONS, PAGE_SIZE, PAGE_SIZE_BITS};
+use crate::heap::HEAP;
+use crate::sync::up::UPSafeCell;
+
+// A piece of memory given to the heap, the slabs of its pages are kept at its start.
+#[derive(Clone, Copy)]
+struct HeapRegion {
+    start: usize,
+    end: usize,
+    slabs: *mut Slab,
+}
+
+struct HeapRegions {
+    regions: [HeapRegion; HEAP_MAX_REGIONS],
+    len: usize,
+}
+
+static HEAP_REGIONS: UPSafeCell<HeapRegions> = UPSafeCell::new(HeapRegions {
+    regions: [HeapRegion { start: 0, end: 0, slabs: core::ptr::null_mut() }; HEAP_MAX_REGIONS],
+    len: 0,
+});
+
+// Register [start, end) as heap memory and put the slabs of its pages at its start,
+// returns where the memory left for allocations begins, `None` if there are too many regions.
+pub unsafe fn add_region(start: usize, end: usize) -> Option<usize> {
+    let mut heap_regions = HEAP_REGIONS.exclusive_access();
+    if heap_regions.len == HEAP_MAX_REGIONS {
+        return None;
+    }
+    let pages = (end - start) / PAGE_SIZE;
+    let slabs = start as *mut Slab;
+    for i in 0..pages {
+        slabs.add(i).write(Slab::empty());
+    }
+    let len = heap_regions.len;
+    heap_regions.regions[len] = HeapRegion { start, end, slabs };
+    heap_regions.len += 1;
+    let slabs_size = pages * core::mem::size_of::<Slab>();
+    Some(start + slabs_size.div_ceil(PAGE_SIZE) * PAGE_SIZE)
+}
+
+// Slab pages come from the buddy allocator of the kernel heap
+pub struct KernelPages;
+
+unsafe impl SlabProvider for KernelPages {
+    fn alloc_page(&mut self) -> *mut u8 {
+        HEAP.buddy_allocator
+            .exclusive_access()
+            .alloc(Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap())
+    }
+
+    unsafe fn dealloc_page(&mut self, page: *mut u8) {
+        HEAP.buddy_allocator
+            .exclusive_access()
+            .dealloc(page, Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap());
+    }
+
+    fn slab_of(&mut self, addr: usize) -> Option<NonNull<Slab>> {
+        let heap_regions = HEAP_REGIONS.exclusive_access();
+        heap_regions.regions[..heap_regions.len]
+            .iter()
+            .find(|region| region.start <= addr && addr < region.end)
+            .and_then(|region| NonNull::new(unsafe { region.slabs.add((addr - region.start) >> PAGE_SIZE_BITS) }))
+    }
+}
@@ -16,10 +16,13 @@ impl Stdout {
 }
 
 impl Stdin {
-    pub fn getchar(&self) -> u8 {
-        let result = UART.receive();
-        Stdout.putchar(result);
-        result
+    /// the next character from the UART, None if none has arrived yet
+    pub fn try_getchar(&self, echo: bool) -> Option<u8> {
+        let result = UART.try_receive()?;
+        if echo {
+            Stdout.putchar(result);
+        }
+        Some(result)
     }
 }
 
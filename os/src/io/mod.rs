@@ -1,5 +1,6 @@
 pub mod console;
 pub mod uart;
+pub mod virtio_blk;
 
 pub fn stdin() -> console::Stdin {
     console::Stdin
@@ -122,13 +122,13 @@ impl MMIOPort {
         }
     }
 
-    // Receives a byte on the serial port.
-    pub fn receive(&self) -> u8 {
+    // Receives a byte on the serial port if one has arrived.
+    pub fn try_receive(&self) -> Option<u8> {
         let rbr_thr = self.rbr_thr.load(Ordering::Relaxed);
-        unsafe {
-            wait_for!(self.line_status().contains(LineStsFlags::INPUT_FULL));
-            rbr_thr.read()
+        if !self.line_status().contains(LineStsFlags::INPUT_FULL) {
+            return None;
         }
+        Some(unsafe { rbr_thr.read() })
     }
 }
 
@@ -0,0 +1,170 @@
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use crate::config::PAGE_SIZE;
+use crate::mm::address::PhysAddr;
+use crate::mm::frame_allocator::{frame_alloc_contiguous, ContiguousFrames};
+
+// Polled driver for a legacy (version 1) virtio-mmio block device,
+// the interface QEMU's virt machine provides by default.
+
+pub const SECTOR_SIZE: usize = 512;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976; // "virt"
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+// MMIO register offsets
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const GUEST_FEATURES: usize = 0x020;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const CONFIG_CAPACITY: usize = 0x100;
+
+// device status bits
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+const BLK_T_IN: u32 = 0;
+const BLK_T_OUT: u32 = 1;
+
+// One request is 3 descriptors (header, data, status), so a small queue is enough.
+const QUEUE_SIZE: usize = 8;
+// Queue memory: descriptors and the available ring in the first page, the used ring
+// in the second. The request header and status byte live in the rest of the first page.
+const AVAIL_OFFSET: usize = QUEUE_SIZE * core::mem::size_of::<Descriptor>();
+const USED_OFFSET: usize = PAGE_SIZE;
+const HEADER_OFFSET: usize = PAGE_SIZE / 2;
+const STATUS_OFFSET: usize = HEADER_OFFSET + core::mem::size_of::<BlkReqHeader>();
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct BlkReqHeader {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+pub struct VirtIOBlk {
+    base: usize,
+    // physically contiguous queue memory, identity mapped in kernel space
+    queue: ContiguousFrames,
+    avail_idx: u16,
+    used_idx: u16,
+    // in sectors
+    capacity: usize,
+}
+
+impl VirtIOBlk {
+    // Set up the device at `base`, `None` if there is no block device there.
+    pub fn probe(base: usize) -> Option<Self> {
+        let read = |offset: usize| unsafe { read_volatile((base + offset) as *const u32) };
+        if read(MAGIC_VALUE) != VIRTIO_MAGIC || read(VERSION) != 1 || read(DEVICE_ID) != VIRTIO_DEVICE_BLOCK {
+            return None;
+        }
+        if (read(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+            return None;
+        }
+        let queue = frame_alloc_contiguous(1)?;
+        let mut blk = Self {
+            base,
+            queue,
+            avail_idx: 0,
+            used_idx: 0,
+            capacity: 0,
+        };
+        // reset, then acknowledge and accept no optional features
+        blk.write_reg(STATUS, 0);
+        blk.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        blk.write_reg(GUEST_FEATURES, 0);
+        blk.write_reg(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        blk.write_reg(QUEUE_SEL, 0);
+        blk.write_reg(QUEUE_NUM, QUEUE_SIZE as u32);
+        blk.write_reg(QUEUE_ALIGN, PAGE_SIZE as u32);
+        blk.write_reg(QUEUE_PFN, blk.queue.ppn.0 as u32);
+        blk.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+        blk.capacity = unsafe { read_volatile((base + CONFIG_CAPACITY) as *const u64) } as usize;
+        Some(blk)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // `buf` must be identity mapped (kernel memory or a physical frame),
+    // its address is handed to the device as is.
+    pub fn read_blocks(&mut self, sector: usize, buf: &mut [u8]) {
+        self.request(BLK_T_IN, sector, buf.as_mut_ptr() as usize, buf.len());
+    }
+
+    pub fn write_blocks(&mut self, sector: usize, buf: &[u8]) {
+        self.request(BLK_T_OUT, sector, buf.as_ptr() as usize, buf.len());
+    }
+
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    // Submit one request and spin until the device has used it.
+    fn request(&mut self, type_: u32, sector: usize, buf: usize, len: usize) {
+        assert!(len % SECTOR_SIZE == 0 && sector + len / SECTOR_SIZE <= self.capacity);
+        let queue: usize = PhysAddr::from(self.queue.ppn).into();
+        let desc = queue as *mut Descriptor;
+        let header = (queue + HEADER_OFFSET) as *mut BlkReqHeader;
+        let status = (queue + STATUS_OFFSET) as *mut u8;
+        // the device writes into the data buffer only for reads
+        let data_flags = if type_ == BLK_T_IN { DESC_F_NEXT | DESC_F_WRITE } else { DESC_F_NEXT };
+        unsafe {
+            write_volatile(header, BlkReqHeader { type_, reserved: 0, sector: sector as u64 });
+            write_volatile(status, 0xff);
+            write_volatile(desc, Descriptor {
+                addr: header as u64,
+                len: core::mem::size_of::<BlkReqHeader>() as u32,
+                flags: DESC_F_NEXT,
+                next: 1,
+            });
+            write_volatile(desc.add(1), Descriptor { addr: buf as u64, len: len as u32, flags: data_flags, next: 2 });
+            write_volatile(desc.add(2), Descriptor { addr: status as u64, len: 1, flags: DESC_F_WRITE, next: 0 });
+            // available ring: flags, idx, ring[QUEUE_SIZE]
+            let avail = (queue + AVAIL_OFFSET) as *mut u16;
+            write_volatile(avail.add(2 + self.avail_idx as usize % QUEUE_SIZE), 0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(avail.add(1), self.avail_idx);
+            fence(Ordering::SeqCst);
+            self.write_reg(QUEUE_NOTIFY, 0);
+            // used ring: flags, idx, ...
+            let used_idx = (queue + USED_OFFSET + 2) as *const u16;
+            while read_volatile(used_idx) == self.used_idx {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            self.used_idx = self.used_idx.wrapping_add(1);
+            self.write_reg(INTERRUPT_ACK, self.read_reg(INTERRUPT_STATUS) & 0x3);
+            assert_eq!(read_volatile(status), 0, "[virtio] block request on sector {} failed", sector);
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::backtrace::print_backtrace;
 use crate::println;
 
+// set by the first panic, a panic while printing the backtrace skips it
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler] // 编译指导属性 与 panic! 宏配合使用
 fn panic(info: &PanicInfo) -> ! {
     if let Some(location) = info.location() {
@@ -13,6 +18,9 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         println!("[kernel]Panicked: {}", info.message().unwrap());
     }
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        print_backtrace();
+    }
     // shutdown(true)
     loop {}
 }
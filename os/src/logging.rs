@@ -0,0 +1,194 @@
+use core::arch::asm;
+use core::fmt::{self, Write};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use crate::config::{LOG_BUFFER_LEVEL, LOG_BUFFER_SIZE};
+use crate::println;
+use crate::sync::up::UPSafeCell;
+use crate::task::processor::PROCESSOR;
+
+// Kernel log backend of the `log` crate. The filter is fixed at build time by
+// the LOG environment variable: a default level and `module=level` overrides,
+// e.g. `make run LOG=warn,mm=debug,syscall::process=trace`. Modules are paths
+// inside the kernel crate, the most specific match wins. Without LOG only
+// INFO and above are printed.
+//
+// Every line up to LOG_BUFFER_LEVEL, and any line printed, is also kept in
+// the log buffer that `sys_syslog` reads, oldest lines are dropped first.
+
+const FILTER: &str = match option_env!("LOG") {
+    Some(filter) => filter,
+    None => "",
+};
+
+/// Directives of FILTER as (module, level), the default level has no module
+fn directives() -> impl Iterator<Item = (Option<&'static str>, LevelFilter)> {
+    FILTER
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .filter_map(|directive| match directive.split_once('=') {
+            Some((module, level)) => Some((Some(module.trim()), level.trim().parse().ok()?)),
+            None => Some((None, directive.parse().ok()?)),
+        })
+}
+
+/// Level enabled for log records of `target`, a module path like `os::mm::swap`
+fn level_for(target: &str) -> LevelFilter {
+    let target = target.strip_prefix("os::").unwrap_or(target);
+    let mut default = LevelFilter::Info;
+    // (length of the module, its level) of the longest module containing `target`
+    let mut best: Option<(usize, LevelFilter)> = None;
+    for (module, level) in directives() {
+        match module {
+            None => default = level,
+            Some(module) => {
+                let module = module.strip_prefix("os::").unwrap_or(module);
+                let matches = target == module
+                    || target.strip_prefix(module).is_some_and(|rest| rest.starts_with("::"));
+                if matches && best.map_or(true, |(len, _)| module.len() >= len) {
+                    best = Some((module.len(), level));
+                }
+            }
+        }
+    }
+    best.map_or(default, |(_, level)| level)
+}
+
+/// Ring buffer of the last LOG_BUFFER_SIZE bytes of log lines, always starting at a line
+pub struct LogBuffer {
+    bytes: [u8; LOG_BUFFER_SIZE],
+    // index of the oldest byte
+    start: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; LOG_BUFFER_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+    fn push(&mut self, byte: u8) {
+        if self.len == LOG_BUFFER_SIZE {
+            // drop the oldest line to make room
+            while self.len > 0 {
+                let dropped = self.bytes[self.start];
+                self.start = (self.start + 1) % LOG_BUFFER_SIZE;
+                self.len -= 1;
+                if dropped == b'\n' {
+                    break;
+                }
+            }
+        }
+        self.bytes[(self.start + self.len) % LOG_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn capacity(&self) -> usize {
+        LOG_BUFFER_SIZE
+    }
+    fn byte(&self, index: usize) -> u8 {
+        self.bytes[(self.start + index) % LOG_BUFFER_SIZE]
+    }
+    /// Copy the newest whole lines that fit into `buf`, returns how many bytes
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut first = self.len - buf.len().min(self.len);
+        // the buffer starts at a line, anywhere later we skip to the next one
+        while first > 0 && first < self.len && self.byte(first - 1) != b'\n' {
+            first += 1;
+        }
+        let count = self.len - first;
+        for (i, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.byte(first + i);
+        }
+        count
+    }
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+impl Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(|byte| self.push(byte));
+        Ok(())
+    }
+}
+
+pub static LOG_BUFFER: UPSafeCell<LogBuffer> = UPSafeCell::new(LogBuffer::new());
+
+/// `[hart,pid]` of a log line
+struct Origin {
+    hart: usize,
+    pid: Option<usize>,
+}
+
+impl Origin {
+    fn current() -> Self {
+        let hart: usize;
+        unsafe { asm!("mv {}, tp", out(reg) hart) };
+        // the processor is borrowed while switching tasks, no pid then
+        let pid = PROCESSOR
+            .try_exclusive_access()
+            .and_then(|processor| processor.clone_curr_task())
+            .map(|task| task.getpid());
+        Self { hart, pid }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "[{},{}]", self.hart, pid),
+            None => write!(f, "[{},-]", self.hart),
+        }
+    }
+}
+
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LOG_BUFFER_LEVEL || metadata.level() <= level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let origin = Origin::current();
+        // a log line from a panic while the buffer is written is only printed
+        if let Some(mut buffer) = LOG_BUFFER.try_exclusive_access() {
+            let _ = writeln!(buffer, "[{:>5}]{} {}", record.level(), origin, record.args());
+        }
+        if record.level() > level_for(record.target()) {
+            return;
+        }
+        let color = match record.level() {
+            Level::Error => 31, // red
+            Level::Warn => 93,  // bright yellow
+            Level::Info => 34,  // blue
+            Level::Debug => 32, // green
+            Level::Trace => 90, // bright black
+        };
+        println!("\u{1B}[{}m[{:>5}]{} {}\u{1B}[0m", color, record.level(), origin, record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: KernelLogger = KernelLogger;
+
+/// Install the logger, after .bss is cleared since the `log` crate keeps its state there
+pub fn init() {
+    log::set_logger(&LOGGER).unwrap();
+    let max = directives()
+        .map(|(_, level)| level)
+        .fold(LevelFilter::Info.max(LOG_BUFFER_LEVEL), |max, level| max.max(level));
+    log::set_max_level(max);
+}
@@ -8,11 +8,15 @@ extern crate alloc;
 // #[macro_use]
 // extern crate bitflags;
 
+mod backtrace;
 mod config;
+mod fs;
 mod heap;
 mod io;
 mod lang_items;
+mod logging;
 mod mm;
+mod random;
 mod sync;
 mod syscall;
 mod task;
@@ -26,12 +30,14 @@ global_asm!(include_str!("link_app.s"));
 
 use config::*;
 use core::arch::asm;
+use log::info;
 // use mm::heap_allocator::HEAP_ALLOCATOR;
 use heap::init_heap;
 use io::uart::UART;
 use riscv::register::*;
 use mm::frame_allocator::init_frame_allocator;
 use mm::memory_set::activate_page_table;
+use mm::swap::init_swap;
 use crate::task::{INITPROC, loader};
 use crate::task::processor::run_tasks;
 use crate::time::init_timer;
@@ -40,27 +46,29 @@ use crate::time::init_timer;
 #[no_mangle]
 pub fn rust_main() {
     init_trap();
-    println!("[kernel] From m mode to s mode");
     clear_bss();
-    println!("[kernel] .bss cleared");
+    logging::init();
+    info!("from m mode to s mode, .bss cleared");
     UART.init();
-    println!("[kernel] UART initialized");
+    info!("UART initialized");
     // HEAP_ALLOCATOR.init();
     init_heap();
-    println!("[kernel] heap initialized");
+    info!("heap initialized");
     init_frame_allocator();
-    println!("[kernel] frame allocator initialized");
+    info!("frame allocator initialized");
     activate_page_table();
-    println!("[kernel] page table activated");
+    info!("page table activated");
+    init_swap();
+    info!("swap initialized");
 
-    println!("[kernel] init task");
+    info!("init task");
     task::add_initproc();
 
     loader::list_apps();
 
-    println!("[kernel] run tasks");
+    info!("run tasks");
     run_tasks();
-    println!("[kernel] tasks finished");
+    info!("tasks finished");
 }
 
 #[no_mangle]
@@ -75,6 +83,9 @@ unsafe fn rust_m2s_mode() {
 
     asm!("csrr tp, mhartid");
 
+    // let S mode read the time counter, the kernel entropy source samples it
+    asm!("csrw mcounteren, {}", in(reg) 0b111usize);
+
     init_timer();
 
     asm!(
@@ -90,6 +101,7 @@ unsafe fn rust_m2s_mode() {
 }
 
 fn init_trap() {
+    trap::set_kernel_trap_entry();
     unsafe {
         sie::set_sext(); // SEIE
         sie::set_stimer(); // STIE
@@ -1,10 +1,17 @@
-use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
+use log::info;
 
 use crate::config::*;
-use crate::println;
 use crate::sync::up::UPSafeCell;
 use super::address::*;
+use super::page_cache::page_cache_shrink;
+use super::swap::{reclaim, swap_stats};
+use crate::task::oom_kill;
+
+/// No frame is left, even after swapping and, outside `without_oom_kill`, killing the largest process
+#[derive(Debug)]
+pub struct OutOfMemory;
 
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
@@ -26,64 +33,220 @@ impl Drop for FrameTracker {
     }
 }
 
+/// `2^order` physically contiguous frames, freed together
+pub struct ContiguousFrames {
+    pub ppn: PhysPageNum,
+    pub order: usize,
+}
+
+impl ContiguousFrames {
+    pub fn new(ppn: PhysPageNum, order: usize) -> Self {
+        for i in 0..1usize << order {
+            for byte in PhysPageNum(ppn.0 + i).get_bytes_array() {
+                *byte = 0;
+            }
+        }
+        Self { ppn, order }
+    }
+}
+
+impl Drop for ContiguousFrames {
+    fn drop(&mut self) {
+        frame_dealloc_contiguous(self.ppn, self.order);
+    }
+}
+
+/// Frame counts of the frame allocator
+#[derive(Clone, Copy, Default)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    // frames in the largest free block, less than `free` when fragmented
+    pub largest_free: usize,
+}
+
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum>;
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize);
+    fn stats(&self) -> FrameStats;
+}
+
+/// Header in the first frame of a free block, linking the free list of its order
+#[derive(Clone, Copy)]
+struct FreeBlock {
+    prev: Option<usize>,
+    next: Option<usize>,
+    order: usize,
+}
+
+fn free_block(ppn: usize) -> &'static mut FreeBlock {
+    PhysPageNum(ppn).get_mut()
 }
 
-pub struct StackFrameAllocator {
-    //空闲内存的起始物理页号
+/// Buddy system over physical page numbers.
+/// A block of order `k` is `2^k` frames starting at a PPN aligned to `2^k`.
+/// The free lists live in the free frames themselves, so the allocator never
+/// needs the kernel heap.
+pub struct BuddyFrameAllocator {
+    //可分配物理页号的范围 [start, end)
     start: usize,
-    //空闲内存的结束物理页号
     end: usize,
-    recycled: Vec<usize>,
+    // first free block of each order, by starting PPN
+    free_lists: [Option<usize>; FRAME_MAX_ORDER + 1],
+    // one bit per frame from `start`, set for the first frame of a free block;
+    // kept in the frames below `start`
+    free_heads: &'static mut [u64],
+    // frames in the free lists
+    free: usize,
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
     pub fn init(&mut self, start: PhysPageNum, end: PhysPageNum) {
-        self.start = start.0;
+        // the bitmap takes the first frames of the range
+        let words = (end.0 - start.0).div_ceil(u64::BITS as usize);
+        let bitmap_pages = (words * core::mem::size_of::<u64>()).div_ceil(PAGE_SIZE);
+        let bitmap = PhysAddr::from(start).0 as *mut u64;
+        self.free_heads = unsafe { core::slice::from_raw_parts_mut(bitmap, words) };
+        self.free_heads.fill(0);
+        self.start = start.0 + bitmap_pages;
         self.end = end.0;
-        println!("[stack] last {} Physical Frames.", self.end - self.start);
+        // carve the range into the largest aligned blocks that fit
+        let mut ppn = self.start;
+        while ppn < self.end {
+            let mut order = (ppn.trailing_zeros() as usize).min(FRAME_MAX_ORDER);
+            while ppn + (1 << order) > self.end {
+                order -= 1;
+            }
+            self.push(ppn, order);
+            ppn += 1 << order;
+        }
+        self.free = self.end - self.start;
+        info!("{} physical frames", self.end - self.start);
+    }
+    fn is_free_head(&self, ppn: usize) -> bool {
+        let bit = ppn - self.start;
+        self.free_heads[bit / 64] & (1 << (bit % 64)) != 0
+    }
+    fn set_free_head(&mut self, ppn: usize, free: bool) {
+        let bit = ppn - self.start;
+        if free {
+            self.free_heads[bit / 64] |= 1 << (bit % 64);
+        } else {
+            self.free_heads[bit / 64] &= !(1 << (bit % 64));
+        }
+    }
+    /// Whether a free block of exactly `order` starts at `ppn`
+    fn is_free_block(&self, ppn: usize, order: usize) -> bool {
+        ppn >= self.start
+            && ppn + (1 << order) <= self.end
+            && self.is_free_head(ppn)
+            && free_block(ppn).order == order
+    }
+    fn push(&mut self, ppn: usize, order: usize) {
+        let next = self.free_lists[order];
+        *free_block(ppn) = FreeBlock { prev: None, next, order };
+        if let Some(next) = next {
+            free_block(next).prev = Some(ppn);
+        }
+        self.free_lists[order] = Some(ppn);
+        self.set_free_head(ppn, true);
+    }
+    fn remove(&mut self, ppn: usize) {
+        let FreeBlock { prev, next, order } = *free_block(ppn);
+        match prev {
+            Some(prev) => free_block(prev).next = next,
+            None => self.free_lists[order] = next,
+        }
+        if let Some(next) = next {
+            free_block(next).prev = prev;
+        }
+        self.set_free_head(ppn, false);
+    }
+    /// Panic if `[ppn, ppn + 2^order)` is not a whole block that is currently allocated
+    fn check_allocated(&self, ppn: usize, order: usize) {
+        let size = 1usize << order;
+        if order > FRAME_MAX_ORDER || ppn % size != 0 || ppn < self.start || ppn + size > self.end {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        }
+        // a free block inside it, or a larger free block containing it
+        let freed = (ppn..ppn + size).any(|p| self.is_free_head(p))
+            || (order + 1..=FRAME_MAX_ORDER).any(|o| {
+                let head = ppn & !((1 << o) - 1);
+                head >= self.start && self.is_free_head(head) && head + (1 << free_block(head).order) > ppn
+            });
+        if freed {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        }
     }
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
             start: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: [None; FRAME_MAX_ORDER + 1],
+            free_heads: &mut [],
+            free: 0,
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else {
-            if self.start == self.end {
-                None
-            } else {
-                self.start += 1;
-                Some((self.start - 1).into())
-            }
-        }
+        self.alloc_contiguous(0)
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        let ppn = ppn.0;
+        self.dealloc_contiguous(ppn, 0)
+    }
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum> {
+        if order > FRAME_MAX_ORDER {
+            return None;
+        }
+        // the smallest free block that is large enough
+        let mut current = (order..=FRAME_MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let ppn = self.free_lists[current].unwrap();
+        self.remove(ppn);
+        // give the upper halves back until the block has the requested size
+        while current > order {
+            current -= 1;
+            self.push(ppn + (1 << current), current);
+        }
+        self.free -= 1 << order;
+        Some(ppn.into())
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize) {
+        let mut ppn = ppn.0;
         // validity check
-        if ppn >= self.start || self.recycled
-            .iter()
-            .any(|&v| v == ppn) {
-            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        self.check_allocated(ppn, order);
+        self.free += 1 << order;
+        // merge with the buddy while it is free
+        let mut order = order;
+        while order < FRAME_MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.remove(buddy);
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.push(ppn, order);
+    }
+    fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.end - self.start,
+            free: self.free,
+            largest_free: (0..=FRAME_MAX_ORDER)
+                .rev()
+                .find(|&o| self.free_lists[o].is_some())
+                .map_or(0, |o| 1 << o),
         }
-        // recycle
-        self.recycled.push(ppn);
     }
 }
 
-
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator> = UPSafeCell::new(StackFrameAllocator::new());
+    pub static ref FRAME_ALLOCATOR: UPSafeCell<BuddyFrameAllocator> = UPSafeCell::new(BuddyFrameAllocator::new());
 }
 
 pub fn init_frame_allocator() {
@@ -95,11 +258,48 @@ pub fn init_frame_allocator() {
         .init(PhysAddr::from(ekernel as usize).ceil(), PhysAddr::from(MEMORY_END).floor());
 }
 
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.exclusive_access().stats()
+}
+
+/// Whether `pages` could be held at all, in frames and swap together. A request
+/// for more fails right away instead of pushing everything else out first.
+pub fn within_memory(pages: usize) -> bool {
+    pages <= frame_stats().total + swap_stats().1
+}
+
+// cleared while a syscall allocates many pages at once
+static OOM_KILL: AtomicBool = AtomicBool::new(true);
+
+/// Run `f` with `frame_alloc` failing instead of killing a process when swapping
+/// cannot free a frame, so one large request gets ENOMEM rather than having other
+/// processes killed for it. `f` has to undo its allocations when it fails.
+pub fn without_oom_kill<T>(f: impl FnOnce() -> T) -> T {
+    let allowed = OOM_KILL.swap(false, Ordering::Relaxed);
+    let result = f();
+    OOM_KILL.store(allowed, Ordering::Relaxed);
+    result
+}
+
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc();
+    // out of frames, drop unused page cache pages or push a user page out
+    // to swap and try again, as a last resort free a whole process
+    ppn.or_else(|| {
+        if page_cache_shrink() || reclaim() {
+            FRAME_ALLOCATOR.exclusive_access().alloc()
+        } else {
+            None
+        }
+    })
+    .or_else(|| {
+        if OOM_KILL.load(Ordering::Relaxed) && oom_kill() {
+            FRAME_ALLOCATOR.exclusive_access().alloc()
+        } else {
+            None
+        }
+    })
+    .map(|ppn| FrameTracker::new(ppn))
 }
 
 fn frame_dealloc(ppn: PhysPageNum) {
@@ -108,3 +308,25 @@ fn frame_dealloc(ppn: PhysPageNum) {
         .dealloc(ppn);
 }
 
+/// Allocate `2^order` contiguous frames, for DMA buffers and huge pages
+pub fn frame_alloc_contiguous(order: usize) -> Option<ContiguousFrames> {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc_contiguous(order)
+        .map(|ppn| ContiguousFrames::new(ppn, order))
+}
+
+/// Like `frame_alloc_contiguous`, but None while the frame allocator is borrowed,
+/// for the kernel heap, which may be asked for memory from inside the allocator
+pub fn try_frame_alloc_contiguous(order: usize) -> Option<ContiguousFrames> {
+    FRAME_ALLOCATOR
+        .try_exclusive_access()?
+        .alloc_contiguous(order)
+        .map(|ppn| ContiguousFrames::new(ppn, order))
+}
+
+fn frame_dealloc_contiguous(ppn: PhysPageNum, order: usize) {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .dealloc_contiguous(ppn, order);
+}
@@ -1,17 +1,41 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
 use crate::config::PAGE_SIZE;
+use crate::fs::Inode;
 use crate::mm::address::{PhysPageNum, VirtAddr, VirtPageNum, VPNRange};
-use crate::mm::frame_allocator::{frame_alloc, FrameTracker};
+use crate::mm::frame_allocator::{frame_alloc, FrameTracker, OutOfMemory};
 use crate::mm::memory_set::{MapPermission, MapType};
-use crate::mm::page_table::{PageTable, PTEFlags};
+use crate::mm::page_table::{PageSize, PageTable, PTEFlags};
 use crate::mm::range::StepByOne;
+use crate::mm::shm::ShmSegment;
+use crate::mm::swap::{swap_alloc, SwapSlot};
 
+/// The file pages a Cached area maps
+#[derive(Clone)]
+pub struct FileMapping {
+    pub inode: Arc<Inode>,
+    // page of the file at the start of the area
+    pub first_page: usize,
+    // the cached frames are mapped as they are, which sys_mmap only allows read-only;
+    // a private mapping copies a page when it is written
+    pub shared: bool,
+}
 
 pub struct MapArea {
     pub vpn_range: VPNRange,
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
-    map_type: MapType,
+    // pages of a Framed area that are out on swap
+    swapped: BTreeMap<VirtPageNum, SwapSlot>,
+    // where the next eviction scan starts
+    clock_hand: VirtPageNum,
+    pub map_type: MapType,
     map_perm: MapPermission,
+    // the segment a Shared area maps
+    shared: Option<Arc<ShmSegment>>,
+    // page cache frames of a Cached area, and the file they belong to;
+    // pages of a private mapping copied on write are in `data_frames` instead
+    cached: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    file: Option<FileMapping>,
 }
 
 impl MapArea {
@@ -26,75 +50,246 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frames: BTreeMap::new(),
+            swapped: BTreeMap::new(),
+            clock_hand: start_vpn,
             map_type,
             map_perm,
+            shared: None,
+            cached: BTreeMap::new(),
+            file: None,
         }
     }
+    /// An area at `start_va` mapping the whole of `segment`
+    pub fn new_shared(start_va: VirtAddr, segment: Arc<ShmSegment>, map_perm: MapPermission) -> Self {
+        let end_va: VirtAddr = (usize::from(start_va) + segment.pages() * PAGE_SIZE).into();
+        let mut area = Self::new(start_va, end_va, MapType::Shared, map_perm);
+        area.shared = Some(segment);
+        area
+    }
+    /// An area mapping page cache frames of `file`, one for each of its pages
+    pub fn new_cached(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        file: FileMapping,
+        frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    ) -> Self {
+        let mut area = Self::new(start_va, end_va, MapType::Cached, map_perm);
+        area.cached = frames;
+        area.file = Some(file);
+        area
+    }
 
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), OutOfMemory> {
         let ppn: PhysPageNum;
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(OutOfMemory)?;
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
+            MapType::Shared => {
+                let segment = self.shared.as_ref().unwrap();
+                ppn = segment.ppn(vpn.0 - self.vpn_range.get_start().0);
+            }
+            MapType::Cached => match self.cached.get(&vpn) {
+                Some(frame) => {
+                    ppn = frame.ppn;
+                    // a private mapping faults on the first write and copies the page
+                    if !self.file.as_ref().unwrap().shared {
+                        pte_flags.remove(PTEFlags::W);
+                    }
+                }
+                // copied on write in the area this one was cloned from, fork fills it in
+                None => {
+                    let frame = frame_alloc().ok_or(OutOfMemory)?;
+                    ppn = frame.ppn;
+                    self.data_frames.insert(vpn, frame);
+                }
+            },
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        page_table.map(vpn, ppn, pte_flags).map_err(|err| {
+            self.data_frames.remove(&vpn);
+            err
+        })
     }
+    /// Unmap the page holding `vpn`, all of it if it is a huge page,
+    /// returns how many 4K pages that was
     #[allow(unused)]
-    pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+    pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> usize {
+        match self.map_type {
+            MapType::Framed => {
+                self.data_frames.remove(&vpn);
+                self.swapped.remove(&vpn);
+            }
+            MapType::Cached => {
+                self.cached.remove(&vpn);
+                self.data_frames.remove(&vpn);
+            }
+            _ => {}
+        }
+        page_table.unmap(vpn).pages()
+    }
+    /// Write one resident page out to swap and free its frame. Pages are visited
+    /// clockwise from where the last scan stopped, a page accessed since it was
+    /// last visited loses its A bit instead. `pinned` pages are skipped.
+    pub fn evict_one(&mut self, page_table: &mut PageTable, pinned: &BTreeSet<VirtPageNum>) -> bool {
+        // only user pages, the trap context has to stay put
+        if self.map_type != MapType::Framed || !self.map_perm.contains(MapPermission::U) {
+            return false;
         }
+        let victim = self
+            .data_frames
+            .range(self.clock_hand..)
+            .chain(self.data_frames.range(..self.clock_hand))
+            .map(|(vpn, _)| *vpn)
+            .find(|vpn| !pinned.contains(vpn) && !page_table.test_and_clear_accessed(*vpn));
+        let vpn = match victim {
+            Some(vpn) => vpn,
+            None => return false,
+        };
+        let slot = match swap_alloc() {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let frame = self.data_frames.remove(&vpn).unwrap();
+        slot.write(frame.ppn);
+        page_table.swap_out(vpn, slot.id);
+        self.swapped.insert(vpn, slot);
+        self.clock_hand = VirtPageNum(vpn.0 + 1);
+        true
+    }
+    /// Read `vpn` back from swap into a new frame, false if it is not swapped out
+    /// or there is no memory to bring it back
+    pub fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        if !self.swapped.contains_key(&vpn) {
+            return false;
+        }
+        let frame = match frame_alloc() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        // the PTE already exists, mapping it again needs no table frame
+        let slot = self.swapped.remove(&vpn).unwrap();
+        slot.read(frame.ppn);
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags).unwrap();
+        self.data_frames.insert(vpn, frame);
+        true
+    }
+    /// Replace the cached page at `vpn` of a private writable file mapping by a copy
+    /// the area owns and may write, false if `vpn` is no such page
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<bool, OutOfMemory> {
+        let private = self.file.as_ref().is_some_and(|file| !file.shared);
+        if !private || !self.map_perm.contains(MapPermission::W) || !self.cached.contains_key(&vpn) {
+            return Ok(false);
+        }
+        let frame = frame_alloc().ok_or(OutOfMemory)?;
+        let cached = self.cached.remove(&vpn).unwrap();
+        frame.ppn.get_bytes_array().copy_from_slice(cached.ppn.get_bytes_array());
+        // the PTE stays, mapping it again needs no table frame
         page_table.unmap(vpn);
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags).unwrap();
+        self.data_frames.insert(vpn, frame);
+        Ok(true)
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// whether `vpn` maps a page cache frame, which is shared rather than copied on fork
+    pub fn is_cached(&self, vpn: VirtPageNum) -> bool {
+        self.cached.contains_key(&vpn)
+    }
+    /// number of frames held by the area
+    pub fn resident_pages(&self) -> usize {
+        self.data_frames.len()
+    }
+    /// the swap slot holding `vpn`, if it is swapped out
+    pub fn swap_slot(&self, vpn: VirtPageNum) -> Option<&SwapSlot> {
+        self.swapped.get(&vpn)
+    }
+    /// Identical areas use the largest pages that fit at each position: a huge page
+    /// needs `vpn` aligned to its size and the whole page inside the area.
+    /// When memory runs out the pages mapped so far are unmapped again.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), OutOfMemory> {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end().0;
+        let mut vpn = start;
+        while vpn.0 < end {
+            let result = if self.map_type == MapType::Identical {
+                let size = PageSize::ALL
+                    .into_iter()
+                    .find(|size| vpn.0 % size.pages() == 0 && vpn.0 + size.pages() <= end)
+                    .unwrap();
+                let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+                page_table
+                    .map_huge(vpn, PhysPageNum(vpn.0), pte_flags, size)
+                    .map(|_| VirtPageNum(vpn.0 + size.pages()))
+            } else {
+                self.map_one(page_table, vpn).map(|_| VirtPageNum(vpn.0 + 1))
+            };
+            match result {
+                Ok(next) => vpn = next,
+                Err(err) => {
+                    self.unmap_range(page_table, start, vpn);
+                    return Err(err);
+                }
+            }
         }
+        Ok(())
     }
-    #[allow(unused)]
-    pub fn unmap(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.unmap_one(page_table, vpn);
+    fn unmap_range(&mut self, page_table: &mut PageTable, start: VirtPageNum, end: VirtPageNum) {
+        let mut vpn = start;
+        while vpn < end {
+            let pages = self.unmap_one(page_table, vpn);
+            vpn = VirtPageNum(vpn.0 + pages);
         }
     }
     #[allow(unused)]
+    pub fn unmap(&mut self, page_table: &mut PageTable) {
+        self.unmap_range(page_table, self.vpn_range.get_start(), self.vpn_range.get_end());
+    }
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
-            self.unmap_one(page_table, vpn)
+            self.unmap_one(page_table, vpn);
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
-    #[allow(unused)]
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn)
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Result<(), OutOfMemory> {
+        let old_end = self.vpn_range.get_end();
+        for vpn in VPNRange::new(old_end, new_end) {
+            if let Err(err) = self.map_one(page_table, vpn) {
+                self.unmap_range(page_table, old_end, vpn);
+                return Err(err);
+            }
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        Ok(())
     }
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    /// copy `data` to the area starting `offset` bytes into its first page,
+    /// the rest of the last touched page is zero-filled
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8], offset: usize) {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
+        let mut page_offset = offset;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
         loop {
-            let src = &data[start..len.min(start + PAGE_SIZE)];
-            let dst = &mut page_table
+            let src = &data[start..len.min(start + PAGE_SIZE - page_offset)];
+            let page = page_table
                 .translate(current_vpn)
                 .unwrap()
                 .ppn()
-                .get_bytes_array()[..src.len()];
-            dst.copy_from_slice(src);
-            start += PAGE_SIZE;
+                .get_bytes_array();
+            page[page_offset..page_offset + src.len()].copy_from_slice(src);
+            start += src.len();
             if start >= len {
+                page[page_offset + src.len()..].fill(0);
                 break;
             }
+            page_offset = 0;
             current_vpn.step();
         }
     }
@@ -102,8 +297,13 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
+            swapped: BTreeMap::new(),
+            clock_hand: another.vpn_range.get_start(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            shared: another.shared.clone(),
+            cached: another.cached.clone(),
+            file: another.file.clone(),
         }
     }
 }
@@ -1,16 +1,27 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
 use bitflags::bitflags;
 use lazy_static::*;
+use log::{debug, trace};
 use riscv::register::satp;
+use xmas_elf::header::{Class, Machine, Type};
+use xmas_elf::program::{ProgramHeader, ProgramHeader64};
+use xmas_elf::ElfFile;
 
 use crate::config::*;
-use crate::mm::map_area::MapArea;
+use crate::fs::image_inode;
+use crate::mm::map_area::{FileMapping, MapArea};
+use crate::mm::page_cache::page_cache_get;
+use crate::mm::shm::ShmSegment;
 use super::address::*;
+use super::frame_allocator::{within_memory, OutOfMemory};
 use super::page_table::{PageTable, PageTableEntry, PTEFlags};
-use crate::println;
+use crate::random::{random_pages, random_u64};
 use crate::sync::up::UPSafeCell;
+use crate::task::loader::get_app_data_by_name;
 
 extern "C" {
     fn stext();
@@ -29,6 +40,10 @@ extern "C" {
 pub enum MapType {
     Identical,
     Framed,
+    // frames of a shared memory segment, owned by the segment
+    Shared,
+    // read-only file pages from the page cache
+    Cached,
 }
 
 bitflags! {
@@ -42,6 +57,69 @@ bitflags! {
 }
 
 
+const R_RISCV_NONE: u64 = 0;
+const R_RISCV_RELATIVE: u64 = 3;
+
+// PT_DYNAMIC entries locating the relocation table
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+// size of an Elf64_Rela
+const RELA_SIZE: usize = 24;
+
+// auxiliary vector entries
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_BASE: usize = 7;
+const AT_ENTRY: usize = 9;
+const AT_HWCAP: usize = 16;
+const AT_RANDOM: usize = 25;
+/// entries of the auxiliary vector, AT_NULL aside
+const AUXV_LEN: usize = 8;
+
+// one bit per single-letter extension ('a' is bit 0) as Linux reports it, RV64IMAFDC
+const HWCAP_RISCV: usize = 1 << (b'i' - b'a')
+    | 1 << (b'm' - b'a')
+    | 1 << (b'a' - b'a')
+    | 1 << (b'f' - b'a')
+    | 1 << (b'd' - b'a')
+    | 1 << (b'c' - b'a');
+
+/// Reasons for `MemorySet::from_elf` to reject an image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ElfError {
+    /// headers or segments can not be parsed or point outside the file
+    Malformed(&'static str),
+    /// not an ELF64 image
+    WrongClass,
+    /// not built for RISC-V
+    WrongMachine,
+    /// neither an executable (ET_EXEC) nor a position-independent (ET_DYN) image
+    NotExecutable,
+    /// `p_align` is not a power of two or disagrees with `p_vaddr`/`p_offset`
+    BadAlignment,
+    /// a segment lies outside the user half of the address space
+    OutOfRange,
+    /// a segment overlaps another segment or the trap context/trampoline pages
+    Overlap,
+    /// a dynamic relocation is not R_RISCV_RELATIVE or does not patch a writable segment of its image
+    BadRelocation,
+    /// the PT_INTERP interpreter is missing or is not position-independent
+    BadInterp,
+    /// no frames left to load the image
+    OutOfMemory,
+}
+
+impl From<OutOfMemory> for ElfError {
+    fn from(_: OutOfMemory) -> Self {
+        ElfError::OutOfMemory
+    }
+}
+
 lazy_static! {
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> = Arc::new( UPSafeCell::new(MemorySet::init_kernel()) );
 }
@@ -49,14 +127,23 @@ lazy_static! {
 pub struct MemorySet {
     page_table: PageTable,
     map_areas: Vec<MapArea>,
+    /// randomized start of the user heap, right above the loaded segments
+    pub heap_bottom: usize,
+    /// end of the user heap, moved by brk
+    pub brk: usize,
+    // pages the running syscall works on, never swapped out
+    pinned: BTreeSet<VirtPageNum>,
 }
 
 impl MemorySet {
-    pub fn new() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    pub fn new() -> Result<Self, OutOfMemory> {
+        Ok(Self {
+            page_table: PageTable::new()?,
             map_areas: Vec::new(),
-        }
+            heap_bottom: 0,
+            brk: 0,
+            pinned: BTreeSet::new(),
+        })
     }
     /// get page_table `level0_ppn`
     pub fn satp(&self) -> usize {
@@ -67,11 +154,11 @@ impl MemorySet {
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> Result<(), OutOfMemory> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
     }
     pub fn remove_areas(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -84,35 +171,68 @@ impl MemorySet {
             self.map_areas.remove(idx);
         }
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        println!(
-            "[Mem] Map area [{:#x}, {:#x})",
+    /// Map `map_area` and fill it with `data`, nothing is left mapped if memory runs out
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) -> Result<(), OutOfMemory> {
+        self.push_with_offset(map_area, data, 0)
+    }
+    /// like `push`, but `data` starts `offset` bytes into the first page
+    fn push_with_offset(
+        &mut self,
+        mut map_area: MapArea,
+        data: Option<&[u8]>,
+        offset: usize,
+    ) -> Result<(), OutOfMemory> {
+        trace!(
+            "map area [{:#x}, {:#x})",
             map_area.vpn_range.get_start().0 << 12,
             map_area.vpn_range.get_end().0 << 12
         );
-        map_area.map(&mut self.page_table);
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+            map_area.copy_data(&mut self.page_table, data, offset);
         }
         self.map_areas.push(map_area);
+        Ok(())
+    }
+    /// whether `vpn_range` intersects an area that has been pushed already
+    fn overlaps(&self, vpn_range: VPNRange) -> bool {
+        self.map_areas
+            .iter()
+            .any(|area| area.vpn_range.intersects(&vpn_range))
+    }
+    /// whether `pages` pages from `start` lie in the user half and are not mapped
+    pub fn range_free(&self, start: VirtPageNum, pages: usize) -> bool {
+        start.0 + pages <= VirtAddr::from(USER_SPACE_END).floor().0
+            && !self.overlaps(VPNRange::new(start, VirtPageNum(start.0 + pages)))
+    }
+    /// The lowest free range of `pages` pages at or above `from`
+    pub fn find_free(&self, from: VirtPageNum, pages: usize) -> Option<VirtPageNum> {
+        let mut start = from;
+        while let Some(area) = self.map_areas.iter().find(|area| {
+            area.vpn_range.get_start().0 < start.0 + pages && start < area.vpn_range.get_end()
+        }) {
+            start = area.vpn_range.get_end();
+        }
+        self.range_free(start, pages).then_some(start)
     }
     /// not collected by areas
-    fn map_trampoline(&mut self) {
+    fn map_trampoline(&mut self) -> Result<(), OutOfMemory> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
     pub fn init_kernel() -> Self {
-        let mut memory_set = Self::new();
+        Self::map_kernel().expect("[kernel] out of memory while mapping the kernel")
+    }
+    fn map_kernel() -> Result<Self, OutOfMemory> {
+        let mut memory_set = Self::new()?;
         // map trampoline
-        println!("mapping trampoline");
-        println!("[kernel] .text [{:#x}, {:#x})", TRAMPOLINE, TRAMPOLINE - 1 + PAGE_SIZE);
-        memory_set.map_trampoline();
+        debug!("mapping trampoline [{:#x}, {:#x})", TRAMPOLINE, TRAMPOLINE - 1 + PAGE_SIZE);
+        memory_set.map_trampoline()?;
         // map kernel sections
-        println!("mapping .text section");
-        println!("[kernel] .text [{:#x}, {:#x})", stext as usize, etext as usize);
+        debug!("mapping .text [{:#x}, {:#x})", stext as usize, etext as usize);
         memory_set.push(
             MapArea::new(
                 (stext as usize).into(),
@@ -121,9 +241,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
-        println!("mapping .rodata section");
-        println!("[kernel] .rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
+        )?;
+        debug!("mapping .rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
         memory_set.push(
             MapArea::new(
                 (srodata as usize).into(),
@@ -132,9 +251,8 @@ impl MemorySet {
                 MapPermission::R,
             ),
             None,
-        );
-        println!("mapping .data section");
-        println!("[kernel] .data [{:#x}, {:#x})", sdata as usize, edata as usize);
+        )?;
+        debug!("mapping .data [{:#x}, {:#x})", sdata as usize, edata as usize);
         memory_set.push(
             MapArea::new(
                 (sdata as usize).into(),
@@ -143,9 +261,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        println!("mapping .bss section");
-        println!("[kernel] .bss [{:#x}, {:#x})", sbss_with_stack as usize, ebss as usize);
+        )?;
+        debug!("mapping .bss [{:#x}, {:#x})", sbss_with_stack as usize, ebss as usize);
         memory_set.push(
             MapArea::new(
                 (sbss_with_stack as usize).into(),
@@ -154,9 +271,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        println!("mapping physical memory");
-        println!("[kernel] physical memory [{:#x}, {:#x})", ekernel as usize, MEMORY_END);
+        )?;
+        debug!("mapping physical memory [{:#x}, {:#x})", ekernel as usize, MEMORY_END);
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
@@ -165,9 +281,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        println!("mapping memory-mapped registers");
-        println!("[kernel] memory-mapped registers for IO [{:#x}, {:#x})", SERIAL_PORT_BASE_ADDRESS, SERIAL_PORT_BASE_ADDRESS + SERIAL_PORT_MAP_SIZE);
+        )?;
+        debug!("mapping UART registers [{:#x}, {:#x})", SERIAL_PORT_BASE_ADDRESS, SERIAL_PORT_BASE_ADDRESS + SERIAL_PORT_MAP_SIZE);
         memory_set.push(
             MapArea::new(
                 SERIAL_PORT_BASE_ADDRESS.into(),
@@ -176,55 +291,95 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        memory_set
+        )?;
+        debug!("mapping virtio registers [{:#x}, {:#x})", VIRTIO0, VIRTIO0 + VIRTIO0_MAP_SIZE);
+        memory_set.push(
+            MapArea::new(
+                VIRTIO0.into(),
+                (VIRTIO0 + VIRTIO0_MAP_SIZE).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        )?;
+        Ok(memory_set)
     }
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new();
+    /// Build a user address space from `elf_data`, returns (memory_set, user_sp, entry_point, tp)
+    ///
+    /// If the image asks for an interpreter (PT_INTERP) it is loaded as well and
+    /// gets control first, the auxiliary vector on the user stack tells it where
+    /// the program is. `args` and `envs` have to fit in ARG_MAX.
+    pub fn from_elf(
+        elf_data: &'static [u8],
+        args: &[String],
+        envs: &[String],
+    ) -> Result<(Self, usize, usize, usize), ElfError> {
+        let elf = parse_elf(elf_data)?;
+        // position-independent images are loaded at a random base
+        let bias = match elf.header.pt2.type_().as_type() {
+            Type::Executable => 0,
+            _ => ELF_DYN_BASE + random_pages(ASLR_PAGES) * PAGE_SIZE,
+        };
+        let mut memory_set = Self::new()?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
+        // relocating writes to the segments, only images loaded as is can share the page cache
+        let mut max_end_vpn = memory_set.map_segments(&elf, bias, bias == 0)?;
+        let (entry_point, interp_base) = match find_interp(&elf)? {
+            Some(interp_data) => {
+                // the interpreter relocates the program, we only relocate the interpreter
+                let interp = parse_elf(interp_data)?;
+                if interp.header.pt2.type_().as_type() != Type::SharedObject {
+                    return Err(ElfError::BadInterp);
                 }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
+                let interp_base = ELF_INTERP_BASE + random_pages(ASLR_PAGES) * PAGE_SIZE;
+                max_end_vpn = max_end_vpn.max(memory_set.map_segments(&interp, interp_base, false)?);
+                memory_set.relocate(&interp, interp_base)?;
+                (interp_base + interp.header.pt2.entry_point() as usize, interp_base)
+            }
+            None => {
+                if bias != 0 {
+                    memory_set.relocate(&elf, bias)?;
                 }
-                println!(
-                    "[Mem] ELF map Virt area [{:#x}, {:#x})",
-                    start_va.0 << 12,
-                    end_va.0 << 12
-                );
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                (bias + elf.header.pt2.entry_point() as usize, 0)
             }
-        }
-        // map user stack with U flags
+        };
+        // the heap starts a random distance above the segments
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        memory_set.heap_bottom = usize::from(max_end_va) + (random_pages(ASLR_PAGES) + 1) * PAGE_SIZE;
+        memory_set.brk = memory_set.heap_bottom;
+        let tls = find_tls(&elf)?;
+        let phdr = find_phdr(&elf);
+        // program headers that are not loaded are copied to the stack
+        let phdr_copy = match phdr {
+            Some(_) => None,
+            None => {
+                let pt2 = &elf.header.pt2;
+                let phdr_offset = pt2.ph_offset() as usize;
+                let phdr_size = pt2.ph_count() as usize * pt2.ph_entry_size() as usize;
+                let bytes = phdr_offset
+                    .checked_add(phdr_size)
+                    .and_then(|phdr_end| elf.input.get(phdr_offset..phdr_end))
+                    .ok_or(ElfError::Malformed("program headers out of file"))?;
+                Some(bytes)
+            }
+        };
+        // the stack is made large enough for what is put on it below, each part
+        // with its worst alignment, and leaves the program USER_STACK_SIZE of it
+        let word = core::mem::size_of::<usize>();
+        let strings: usize = args.iter().chain(envs).map(|string| string.len() + 1).sum();
+        let words = 1 + (args.len() + 1) + (envs.len() + 1) + 2 * (AUXV_LEN + 1);
+        let initial = strings
+            + 16
+            + tls.map_or(0, |(_, mem_size, align)| mem_size + align)
+            + phdr_copy.map_or(0, |bytes| bytes.len() + word)
+            + words * word
+            + 16;
+        let user_stack_size = USER_STACK_SIZE + initial.next_multiple_of(PAGE_SIZE);
+        // map user stack with U flags near the top of user space, below a guard page
+        let user_stack_top = USER_SPACE_END - (random_pages(ASLR_PAGES) + 1) * PAGE_SIZE;
+        let user_stack_bottom = user_stack_top - user_stack_size;
         memory_set.push(
             MapArea::new(
                 user_stack_bottom.into(),
@@ -233,7 +388,59 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
+        let mut user_sp = user_stack_top;
+        // the strings argv and envp point to, NUL-terminated
+        let mut push_strings = |strings: &[String]| {
+            strings
+                .iter()
+                .map(|string| {
+                    user_sp -= string.len() + 1;
+                    memory_set.copy_to_user(user_sp, string.as_bytes());
+                    memory_set.copy_to_user(user_sp + string.len(), &[0]);
+                    user_sp
+                })
+                .collect::<Vec<_>>()
+        };
+        let envp = push_strings(envs);
+        let argv = push_strings(args);
+        // 16 random bytes for AT_RANDOM, user space seeds its stack canary from them
+        user_sp -= 16;
+        let random_bytes = [random_u64().to_ne_bytes(), random_u64().to_ne_bytes()].concat();
+        memory_set.copy_to_user(user_sp, &random_bytes);
+        let at_random = user_sp;
+        // TLS block of the main thread, initialized from the PT_TLS template;
+        // tp points at its start (TLS variant I), 0 if the program has none
+        let tp = match tls {
+            Some((template, mem_size, align)) => {
+                user_sp = (user_sp - mem_size) & !(align - 1);
+                // fresh stack frames are zeroed, which covers .tbss
+                memory_set.copy_to_user(user_sp, template);
+                user_sp
+            }
+            None => 0,
+        };
+        let phdr = match phdr {
+            Some(phdr) => bias + phdr,
+            // program headers are not loaded, give a copy on the stack instead
+            None => {
+                let bytes = phdr_copy.unwrap();
+                user_sp = (user_sp - bytes.len()) & !(word - 1);
+                memory_set.copy_to_user(user_sp, bytes);
+                user_sp
+            }
+        };
+        let auxv: [(usize, usize); AUXV_LEN] = [
+            (AT_PHDR, phdr),
+            (AT_PHENT, elf.header.pt2.ph_entry_size() as usize),
+            (AT_PHNUM, elf.header.pt2.ph_count() as usize),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_BASE, interp_base),
+            (AT_ENTRY, bias + elf.header.pt2.entry_point() as usize),
+            (AT_HWCAP, HWCAP_RISCV),
+            (AT_RANDOM, at_random),
+        ];
+        user_sp = memory_set.push_initial_stack(user_sp, &argv, &envp, &auxv);
         // map TrapContext
         memory_set.push(
             MapArea::new(
@@ -243,33 +450,178 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        )?;
+        Ok((memory_set, user_sp, entry_point, tp))
+    }
+    /// Map every `Load` segment of `elf` shifted by `bias`, returns the end of the highest one
+    ///
+    /// With `cached`, read-only segments fully backed by the file map its page cache
+    /// frames instead of private copies.
+    fn map_segments(&mut self, elf: &ElfFile<'static>, bias: usize, cached: bool) -> Result<VirtPageNum, ElfError> {
+        let ph_count = elf.header.pt2.ph_count();
+        let mut max_end_vpn = VirtPageNum(0);
+        for i in 0..ph_count {
+            let ph = elf.program_header(i).map_err(ElfError::Malformed)?;
+            if ph.get_type().map_err(ElfError::Malformed)? != xmas_elf::program::Type::Load {
+                continue;
+            }
+            check_segment(&ph, elf.input.len(), bias)?;
+            let start_va: VirtAddr = (bias + ph.virtual_addr() as usize).into();
+            let end_va: VirtAddr = (bias + (ph.virtual_addr() + ph.mem_size()) as usize).into();
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+            if self.overlaps(map_area.vpn_range) {
+                return Err(ElfError::Overlap);
+            }
+            let shareable = !map_perm.contains(MapPermission::W)
+                && ph.file_size() == ph.mem_size()
+                && start_va.page_offset() == ph.offset() as usize % PAGE_SIZE;
+            if cached && shareable {
+                // file page of the first page of the segment
+                let first_page = ph.offset() as usize / PAGE_SIZE;
+                let inode = image_inode(elf.input);
+                let mut frames = BTreeMap::new();
+                for (i, vpn) in map_area.vpn_range.into_iter().enumerate() {
+                    frames.insert(vpn, page_cache_get(&inode, first_page + i)?);
+                }
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+                let file = FileMapping { inode, first_page, shared: false };
+                self.push(MapArea::new_cached(start_va, end_va, map_perm, file, frames), None)?;
+                continue;
+            }
+            trace!(
+                "ELF segment [{:#x}, {:#x})",
+                start_va.0,
+                end_va.0
+            );
+            max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+            // bytes in [file_size, mem_size) are .bss, copy_data leaves them zeroed
+            self.push_with_offset(
+                map_area,
+                Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
+                start_va.page_offset(),
+            )?;
+        }
+        Ok(max_end_vpn)
+    }
+    /// Apply the dynamic relocations of an image loaded at `bias`, only
+    /// R_RISCV_RELATIVE is needed for static PIE. The table is found through
+    /// PT_DYNAMIC, section headers are optional.
+    fn relocate(&mut self, elf: &ElfFile, bias: usize) -> Result<(), ElfError> {
+        let relas = match find_relas(elf)? {
+            Some(relas) => relas,
+            None => return Ok(()),
+        };
+        // relocations may only patch the writable segments of this very image
+        let writable: Vec<(usize, usize)> = elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load) && ph.flags().is_write())
+            .map(|ph| {
+                let start = bias + ph.virtual_addr() as usize;
+                (start, start + ph.mem_size() as usize)
+            })
+            .collect();
+        for rela in relas.chunks_exact(RELA_SIZE) {
+            let offset = read_u64(rela, 0) as usize;
+            let info = read_u64(rela, 8);
+            let addend = read_u64(rela, 16) as usize;
+            match info & 0xffff_ffff {
+                R_RISCV_NONE => continue,
+                R_RISCV_RELATIVE => {}
+                _ => return Err(ElfError::BadRelocation),
+            }
+            let va = bias.checked_add(offset).ok_or(ElfError::BadRelocation)?;
+            let size = core::mem::size_of::<usize>();
+            if va % size != 0
+                || !writable
+                    .iter()
+                    .any(|&(start, end)| start <= va && va.checked_add(size).is_some_and(|word_end| word_end <= end))
+            {
+                return Err(ElfError::BadRelocation);
+            }
+            let pa = self
+                .page_table
+                .translate_va(VirtAddr::from(va))
+                .ok_or(ElfError::BadRelocation)?;
+            *pa.get_mut::<usize>() = bias.wrapping_add(addend);
+        }
+        Ok(())
+    }
+    /// Copy `data` to the mapped user pages starting at `va`
+    fn copy_to_user(&self, va: usize, data: &[u8]) {
+        let mut start = 0;
+        while start < data.len() {
+            let va = VirtAddr::from(va + start);
+            let len = (PAGE_SIZE - va.page_offset()).min(data.len() - start);
+            let ppn = self.page_table.translate(va.floor()).unwrap().ppn();
+            ppn.get_bytes_array()[va.page_offset()..va.page_offset() + len]
+                .copy_from_slice(&data[start..start + len]);
+            start += len;
+        }
+    }
+    /// Lay out the initial stack below `sp` the way the Linux ABI does and return the new sp:
+    /// argc, `argv` and `envp` each closed by a null pointer, then the `auxv` pairs closed by AT_NULL
+    fn push_initial_stack(&self, sp: usize, argv: &[usize], envp: &[usize], auxv: &[(usize, usize)]) -> usize {
+        let mut words: Vec<usize> = Vec::new();
+        words.push(argv.len());
+        words.extend_from_slice(argv);
+        words.push(0);
+        words.extend_from_slice(envp);
+        words.push(0);
+        for &(key, value) in auxv {
+            words.extend_from_slice(&[key, value]);
+        }
+        words.extend_from_slice(&[AT_NULL, 0]);
+        let size = words.len() * core::mem::size_of::<usize>();
+        // sp is 16-byte aligned at entry
+        let sp = (sp - size) & !0xf;
+        let bytes = unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, size) };
+        self.copy_to_user(sp, bytes);
+        sp
     }
 
     /// Clone a `MemorySet`
-    pub fn copy_from_user(user_space: &Self) -> Self {
-        let mut memory_set = Self::new();
+    pub fn copy_from_user(user_space: &Self) -> Result<Self, OutOfMemory> {
+        let mut memory_set = Self::new()?;
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // copy data sections/trap_context/user_stack
         for area in user_space.map_areas.iter() {
             let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
+            memory_set.push(new_area, None)?;
+            // shared frames are mapped again, not copied
+            if area.map_type == MapType::Shared {
+                continue;
+            }
+            // copy data from another space, swapped out pages straight from swap
             for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                if area.is_cached(vpn) {
+                    continue;
+                }
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                if let Some(slot) = area.swap_slot(vpn) {
+                    slot.read(dst_ppn);
+                    continue;
+                }
+                let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 dst_ppn
                     .get_bytes_array()
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
-        memory_set
+        Ok(memory_set)
     }
 
     pub fn activate(&self) {
@@ -284,6 +636,185 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
+    /// Map all of `segment` at the lowest free address above SHM_BASE, returns the address
+    pub fn attach_shm(&mut self, segment: Arc<ShmSegment>, permission: MapPermission) -> Result<usize, OutOfMemory> {
+        // out of address space is ENOMEM as well
+        let start = self
+            .find_free(VirtAddr::from(SHM_BASE).floor(), segment.pages())
+            .ok_or(OutOfMemory)?;
+        let va: VirtAddr = start.into();
+        self.push(MapArea::new_shared(va, segment, permission), None)?;
+        Ok(va.into())
+    }
+    /// Unmap the shared memory attached at `va`, false if there is none
+    pub fn detach_shm(&mut self, va: VirtAddr) -> bool {
+        let start = va.floor();
+        if va.page_offset() != 0
+            || !self
+                .map_areas
+                .iter()
+                .any(|area| area.map_type == MapType::Shared && area.vpn_range.get_start() == start)
+        {
+            return false;
+        }
+        self.remove_areas(start);
+        true
+    }
+    /// Map `pages` zeroed pages at `start`, which `range_free` has to allow
+    pub fn map_anonymous(&mut self, start: VirtPageNum, pages: usize, permission: MapPermission) -> Result<(), OutOfMemory> {
+        let end = VirtPageNum(start.0 + pages);
+        self.with_own_reclaim(|memory_set| {
+            memory_set.push(MapArea::new(start.into(), end.into(), MapType::Framed, permission), None)
+        })
+    }
+    /// Map `pages` pages of `file` at `start`, which `range_free` has to allow
+    pub fn map_file(
+        &mut self,
+        start: VirtPageNum,
+        pages: usize,
+        permission: MapPermission,
+        file: FileMapping,
+    ) -> Result<(), OutOfMemory> {
+        let end = VirtPageNum(start.0 + pages);
+        self.with_own_reclaim(|memory_set| {
+            let mut frames = BTreeMap::new();
+            for (i, vpn) in VPNRange::new(start, end).into_iter().enumerate() {
+                frames.insert(vpn, page_cache_get(&file.inode, file.first_page + i)?);
+            }
+            let area = MapArea::new_cached(start.into(), end.into(), permission, file.clone(), frames);
+            memory_set.push(area, None)
+        })
+    }
+    /// Unmap the mmap mapping of exactly `pages` pages at `start`, false if there is none.
+    /// The heap is moved with `set_brk` instead.
+    pub fn unmap_mapping(&mut self, start: VirtPageNum, pages: usize) -> bool {
+        let heap_start = VirtAddr::from(self.heap_bottom).floor();
+        if start == heap_start
+            || !self.map_areas.iter().any(|area| {
+                (area.map_type == MapType::Framed || area.map_type == MapType::Cached)
+                    && area.vpn_range.get_start() == start
+                    && area.vpn_range.get_end().0 == start.0 + pages
+            })
+        {
+            return false;
+        }
+        self.remove_areas(start);
+        true
+    }
+    /// Move the end of the heap to `brk` and return the new end. The heap is
+    /// left as it is, and the old end returned, if `brk` is below its start, runs
+    /// into another mapping or memory runs out.
+    pub fn set_brk(&mut self, brk: usize) -> usize {
+        if brk < self.heap_bottom || brk > MMAP_BASE {
+            return self.brk;
+        }
+        let heap_start = VirtAddr::from(self.heap_bottom).floor();
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(brk).ceil();
+        if new_end > old_end
+            && (!within_memory(new_end.0 - old_end.0) || self.overlaps(VPNRange::new(old_end, new_end)))
+        {
+            return self.brk;
+        }
+        let result = self.with_own_reclaim(|memory_set| {
+            let page_table = &mut memory_set.page_table;
+            let heap = memory_set
+                .map_areas
+                .iter_mut()
+                .find(|area| area.vpn_range.get_start() == heap_start);
+            match heap {
+                Some(heap) if new_end > old_end => heap.append_to(page_table, new_end),
+                Some(heap) => {
+                    heap.shrink_to(page_table, new_end);
+                    Ok(())
+                }
+                // the heap area is created the first time it grows
+                None => memory_set.push(
+                    MapArea::new(
+                        heap_start.into(),
+                        new_end.into(),
+                        MapType::Framed,
+                        MapPermission::R | MapPermission::W | MapPermission::U,
+                    ),
+                    None,
+                ),
+            }
+        });
+        if result.is_ok() {
+            self.brk = brk;
+        }
+        self.brk
+    }
+    /// frames held by the user pages, what the OOM killer compares
+    pub fn resident_pages(&self) -> usize {
+        self.map_areas.iter().map(|area| area.resident_pages()).sum()
+    }
+    /// Push one of the unpinned user pages out to swap, see `MapArea::evict_one`
+    pub fn evict_one(&mut self) -> bool {
+        let page_table = &mut self.page_table;
+        let pinned = &self.pinned;
+        self.map_areas
+            .iter_mut()
+            .any(|area| area.evict_one(page_table, pinned))
+    }
+    /// Run `grow` again after swapping out one of our own pages for as long as it
+    /// runs out of memory. `reclaim` cannot take pages from an address space that is
+    /// borrowed, which it is while it grows, so a single large process would run out
+    /// of frames with swap to spare. `grow` has to undo itself when it fails.
+    fn with_own_reclaim<T>(&mut self, mut grow: impl FnMut(&mut Self) -> Result<T, OutOfMemory>) -> Result<T, OutOfMemory> {
+        loop {
+            match grow(self) {
+                // the first pass over the pages may only clear A bits
+                Err(OutOfMemory) if self.evict_one() || self.evict_one() => continue,
+                result => return result,
+            }
+        }
+    }
+    /// Bring `vpn` back from swap, false if it is not a swapped out page
+    /// or there is no memory for it
+    pub fn swap_in(&mut self, vpn: VirtPageNum) -> bool {
+        if !self.map_areas.iter().any(|area| area.swap_slot(vpn).is_some()) {
+            return false;
+        }
+        self.with_own_reclaim(|memory_set| {
+            let page_table = &mut memory_set.page_table;
+            let area = memory_set
+                .map_areas
+                .iter_mut()
+                .find(|area| area.swap_slot(vpn).is_some())
+                .unwrap();
+            if area.swap_in(page_table, vpn) {
+                Ok(())
+            } else {
+                Err(OutOfMemory)
+            }
+        })
+        .is_ok()
+    }
+    /// Give a private file mapping its own copy of the page at `vpn`, false if it
+    /// is not such a page or there is no memory for it
+    pub fn copy_on_write(&mut self, vpn: VirtPageNum) -> bool {
+        self.with_own_reclaim(|memory_set| {
+            let page_table = &mut memory_set.page_table;
+            match memory_set
+                .map_areas
+                .iter_mut()
+                .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+            {
+                Some(area) => area.copy_on_write(page_table, vpn),
+                None => Ok(false),
+            }
+        })
+        .unwrap_or(false)
+    }
+    /// Keep `vpn` resident until `unpin_all`, bringing it back if it is out on swap
+    pub fn pin(&mut self, vpn: VirtPageNum) {
+        self.pinned.insert(vpn);
+        self.swap_in(vpn);
+    }
+    pub fn unpin_all(&mut self) {
+        self.pinned.clear();
+    }
     pub fn clear(&mut self) {
         self.map_areas.clear();
     }
@@ -293,6 +824,178 @@ impl MemorySet {
     }
 }
 
+/// Parse `elf_data` and check that it is an image this kernel can run
+fn parse_elf(elf_data: &'static [u8]) -> Result<ElfFile<'static>, ElfError> {
+    let elf = ElfFile::new(elf_data).map_err(ElfError::Malformed)?;
+    xmas_elf::header::sanity_check(&elf).map_err(ElfError::Malformed)?;
+    let elf_header = elf.header;
+    if elf_header.pt1.class() != Class::SixtyFour {
+        return Err(ElfError::WrongClass);
+    }
+    if elf_header.pt2.machine().as_machine() != Machine::RISC_V {
+        return Err(ElfError::WrongMachine);
+    }
+    match elf_header.pt2.type_().as_type() {
+        Type::Executable | Type::SharedObject => {}
+        _ => return Err(ElfError::NotExecutable),
+    }
+    if elf_header.pt2.ph_entry_size() as usize != core::mem::size_of::<ProgramHeader64>() {
+        return Err(ElfError::Malformed("program header size mismatch"));
+    }
+    Ok(elf)
+}
+
+/// Find the image of the interpreter named by PT_INTERP, apps are looked up by file name
+fn find_interp<'a>(elf: &ElfFile) -> Result<Option<&'a [u8]>, ElfError> {
+    let ph = match elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
+    {
+        Some(ph) => ph,
+        None => return Ok(None),
+    };
+    let start = ph.offset() as usize;
+    let end = start.saturating_add(ph.file_size() as usize);
+    if end > elf.input.len() {
+        return Err(ElfError::Malformed("interpreter path out of file"));
+    }
+    let path = &elf.input[start..end];
+    let path = &path[..path.iter().position(|&c| c == 0).unwrap_or(path.len())];
+    let path = core::str::from_utf8(path).map_err(|_| ElfError::BadInterp)?;
+    let name = path.rsplit('/').next().unwrap_or(path);
+    get_app_data_by_name(name).map(Some).ok_or(ElfError::BadInterp)
+}
+
+/// The PT_TLS segment as (initialization template, block size, alignment)
+fn find_tls<'a>(elf: &ElfFile<'a>) -> Result<Option<(&'a [u8], usize, usize)>, ElfError> {
+    let ph = match elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Tls))
+    {
+        Some(ph) => ph,
+        None => return Ok(None),
+    };
+    let (offset, file_size, mem_size) = (ph.offset() as usize, ph.file_size() as usize, ph.mem_size() as usize);
+    let align = (ph.align() as usize).max(core::mem::size_of::<usize>());
+    if file_size > mem_size || offset.saturating_add(file_size) > elf.input.len() {
+        return Err(ElfError::Malformed("TLS segment out of file"));
+    }
+    // it has to fit on the user stack with room to spare
+    if !align.is_power_of_two() || align > PAGE_SIZE || mem_size > USER_STACK_SIZE / 2 {
+        return Err(ElfError::BadAlignment);
+    }
+    Ok(Some((&elf.input[offset..offset + file_size], mem_size, align)))
+}
+
+/// The bytes of the RELA table PT_DYNAMIC points to, checked to lie in the file
+fn find_relas<'a>(elf: &ElfFile<'a>) -> Result<Option<&'a [u8]>, ElfError> {
+    let ph = match elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Dynamic))
+    {
+        Some(ph) => ph,
+        None => return Ok(None),
+    };
+    let (start, size) = (ph.offset() as usize, ph.file_size() as usize);
+    let dynamic = start
+        .checked_add(size)
+        .and_then(|end| elf.input.get(start..end))
+        .ok_or(ElfError::Malformed("dynamic segment out of file"))?;
+    let (mut rela, mut rela_size, mut rela_entry) = (None, 0, RELA_SIZE as u64);
+    for entry in dynamic.chunks_exact(16) {
+        let value = read_u64(entry, 8);
+        match read_u64(entry, 0) {
+            DT_NULL => break,
+            DT_RELA => rela = Some(value),
+            DT_RELASZ => rela_size = value,
+            DT_RELAENT => rela_entry = value,
+            _ => {}
+        }
+    }
+    let rela = match rela {
+        Some(rela) if rela_size > 0 => rela,
+        _ => return Ok(None),
+    };
+    if rela_entry != RELA_SIZE as u64 || rela_size % RELA_SIZE as u64 != 0 {
+        return Err(ElfError::Malformed("bad relocation entry size"));
+    }
+    // DT_RELA is an address, the table is found in the file through the segment holding it
+    let offset = elf
+        .program_iter()
+        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .find(|ph| {
+            ph.virtual_addr() <= rela
+                && rela
+                    .checked_add(rela_size)
+                    .is_some_and(|end| end <= ph.virtual_addr().saturating_add(ph.file_size()))
+        })
+        .map(|ph| ph.offset() + (rela - ph.virtual_addr()))
+        .ok_or(ElfError::Malformed("relocation table outside the loaded segments"))?;
+    offset
+        .checked_add(rela_size)
+        .and_then(|end| elf.input.get(offset as usize..end as usize))
+        .map(Some)
+        .ok_or(ElfError::Malformed("relocation table out of file"))
+}
+
+/// The little-endian u64 at `offset` of `bytes`, which has to be long enough
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Virtual address (before relocation) the program headers are loaded at, if they are
+fn find_phdr(elf: &ElfFile) -> Option<usize> {
+    if let Some(ph) = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Phdr))
+    {
+        return Some(ph.virtual_addr() as usize);
+    }
+    let pt2 = &elf.header.pt2;
+    let phdr_start = pt2.ph_offset();
+    let phdr_end = phdr_start + pt2.ph_count() as u64 * pt2.ph_entry_size() as u64;
+    elf.program_iter()
+        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .find(|ph| ph.offset() <= phdr_start && phdr_end <= ph.offset() + ph.file_size())
+        .map(|ph| (ph.virtual_addr() + phdr_start - ph.offset()) as usize)
+}
+
+/// Validate a `Load` segment before it is mapped into user space
+fn check_segment(ph: &ProgramHeader, elf_len: usize, bias: usize) -> Result<(), ElfError> {
+    if ph.file_size() > ph.mem_size() {
+        return Err(ElfError::Malformed("segment file size exceeds its memory size"));
+    }
+    match ph.offset().checked_add(ph.file_size()) {
+        Some(end) if end <= elf_len as u64 => {}
+        _ => return Err(ElfError::Malformed("segment data out of file")),
+    }
+    let align = ph.align();
+    if align > 1 && (!align.is_power_of_two() || ph.virtual_addr() % align != ph.offset() % align) {
+        return Err(ElfError::BadAlignment);
+    }
+    let start = match (ph.virtual_addr() as usize).checked_add(bias) {
+        Some(start) => start,
+        None => return Err(ElfError::OutOfRange),
+    };
+    let end = match start.checked_add(ph.mem_size() as usize) {
+        Some(end) => end,
+        None => return Err(ElfError::OutOfRange),
+    };
+    // addresses are truncated to 39 bits, so a high segment could alias the trap context
+    let vpn_range = VPNRange::new(VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+    let reserved = VPNRange::new(
+        VirtAddr::from(TRAP_CONTEXT).floor(),
+        VirtPageNum(VirtAddr::from(TRAMPOLINE).floor().0 + 1),
+    );
+    if vpn_range.intersects(&reserved) {
+        return Err(ElfError::Overlap);
+    }
+    if end > USER_SPACE_END {
+        return Err(ElfError::OutOfRange);
+    }
+    Ok(())
+}
+
 pub fn activate_page_table() {
     KERNEL_SPACE.exclusive_access().activate();
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 pub mod address;
 pub mod frame_allocator;
-mod map_area;
+pub mod map_area;
 pub mod memory_set;
+pub mod page_cache;
 pub mod page_table;
 mod range;
+pub mod shm;
+pub mod swap;
+pub mod user_ptr;
 // pub mod heap_allocator;
 
@@ -0,0 +1,74 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::arch::asm;
+use lazy_static::*;
+use crate::config::USER_SPACE_END;
+use crate::fs::{Inode, InodeId};
+use crate::mm::address::VirtAddr;
+use crate::mm::frame_allocator::{frame_alloc, FrameTracker, OutOfMemory};
+use crate::sync::up::UPSafeCell;
+use crate::task::processor::curr_task;
+
+// Page cache: one frame per (inode, page offset) of a file, shared by read and every
+// mapping of that page, program text included. Files are never written, private
+// mappings map the frames read-only and copy a page the first time it is written,
+// so pages nobody maps can be dropped and read again without losing anything.
+
+pub struct PageCache {
+    pages: BTreeMap<(InodeId, usize), Arc<FrameTracker>>,
+}
+
+impl PageCache {
+    fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+        }
+    }
+    /// Drop pages nobody maps any more, returns how many frames were freed
+    fn shrink(&mut self) -> usize {
+        let before = self.pages.len();
+        self.pages.retain(|_, frame| Arc::strong_count(frame) > 1);
+        before - self.pages.len()
+    }
+}
+
+lazy_static! {
+    pub static ref PAGE_CACHE: UPSafeCell<PageCache> = UPSafeCell::new(PageCache::new());
+}
+
+/// The cached frame holding page `page` of `inode`, read in on a miss.
+/// Bytes past the end of the file read as zero.
+pub fn page_cache_get(inode: &Inode, page: usize) -> Result<Arc<FrameTracker>, OutOfMemory> {
+    let key = (inode.id, page);
+    if let Some(frame) = PAGE_CACHE.exclusive_access().pages.get(&key) {
+        return Ok(frame.clone());
+    }
+    // the cache is not borrowed while allocating, frame_alloc may shrink it
+    let frame = Arc::new(frame_alloc().ok_or(OutOfMemory)?);
+    inode.read_page(page, frame.ppn.get_bytes_array());
+    PAGE_CACHE.exclusive_access().pages.insert(key, frame.clone());
+    Ok(frame)
+}
+
+/// Free the cached pages no mapping uses, called when frames run out
+pub fn page_cache_shrink() -> bool {
+    PAGE_CACHE
+        .try_exclusive_access()
+        .is_some_and(|mut cache| cache.shrink() > 0)
+}
+
+/// Give the running task its own copy of the page at `va` if it is a cached page
+/// of a private file mapping it may write to, returns false if it is not
+pub fn copy_on_write_current(va: usize) -> bool {
+    let copied = match curr_task() {
+        Some(task) if va < USER_SPACE_END => {
+            task.inner_exclusive_access().usr_mem.copy_on_write(VirtAddr::from(va).floor())
+        }
+        _ => false,
+    };
+    if copied {
+        // the read-only mapping of the cached frame may still be in the TLB
+        unsafe { asm!("sfence.vma") };
+    }
+    copied
+}
@@ -1,11 +1,9 @@
-use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
 use core::cmp::PartialEq;
+use log::trace;
 use crate::mm::frame_allocator::*;
-use crate::mm::range::StepByOne;
-use crate::println;
 use super::address::*;
 
 bitflags! {
@@ -36,6 +34,11 @@ impl PartialEq for PTEFlags {
 
 // |64    54|53  28|27  19|18  10|9 8|7|6|5|4|3|2|1|0|
 // |Reserved|PPN[2]|PPN[1]|PPN[0]|RSW|D|A|G|U|X|W|R|V|
+//
+// A swapped-out page has V clear, the first RSW bit set and its swap slot in the PPN field;
+// the permission bits are kept for when it comes back.
+const PTE_SWAPPED: usize = 1 << 8;
+
 impl PageTableEntry {
     pub fn new(ppn: PhysPageNum, flags: PTEFlags) -> Self {
         PageTableEntry {
@@ -62,9 +65,65 @@ impl PageTableEntry {
     pub fn writable(&self) -> bool {
         (self.flags() & PTEFlags::W) != PTEFlags::empty()
     }
+    pub fn user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn swapped(slot: usize, flags: PTEFlags) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED | (flags - PTEFlags::V).bits() as usize,
+        }
+    }
+    // swap slot of a page that was swapped out
+    pub fn swap_slot(&self) -> Option<usize> {
+        if !self.is_valid() && self.bits & PTE_SWAPPED != 0 {
+            Some(self.bits >> 10 & ((1usize << 44) - 1))
+        } else {
+            None
+        }
+    }
+    // a valid PTE with any of R/W/X maps memory, otherwise it points to the next level
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+}
+
+/// Sizes of a leaf mapping in Sv39, a leaf one level up covers 512 times as much
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    // largest first, the order `MapArea` tries them in
+    pub const ALL: [PageSize; 3] = [PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+    /// number of 4K pages covered
+    pub fn pages(&self) -> usize {
+        match self {
+            PageSize::Size4K => 1,
+            PageSize::Size2M => 1 << 9,
+            PageSize::Size1G => 1 << 18,
+        }
+    }
+    // index into `VirtPageNum::indexes` of the PTE that maps a page of this size
+    fn level(&self) -> usize {
+        match self {
+            PageSize::Size1G => 0,
+            PageSize::Size2M => 1,
+            PageSize::Size4K => 2,
+        }
+    }
+    fn from_level(level: usize) -> Self {
+        match level {
+            0 => PageSize::Size1G,
+            1 => PageSize::Size2M,
+            _ => PageSize::Size4K,
+        }
+    }
 }
 
 pub struct PageTable {
@@ -73,45 +132,48 @@ pub struct PageTable {
 }
 
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        println!(
-            "[page_table] PPN of page table root is {:#x}.",
+    pub fn new() -> Result<Self, OutOfMemory> {
+        let frame = frame_alloc().ok_or(OutOfMemory)?;
+        trace!(
+            "page table root at {:#x}",
             usize::from(frame.ppn) << 12
         );
-        PageTable {
+        Ok(PageTable {
             level0_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
-    fn create_pte(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// Walk down to the PTE that maps a `size` page at `vpn`, creating tables on the way
+    fn create_pte(&mut self, vpn: VirtPageNum, size: PageSize) -> Result<&mut PageTableEntry, OutOfMemory> {
         let idx = vpn.indexes();
         let mut ppn = self.level0_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for i in 0..3 {
             let pte = &mut ppn.get_pte_array()[idx[i]];
-            if i == 2 {
+            if i == size.level() {
                 result = Some(pte);
                 break;
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is inside a huge page", vpn);
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(OutOfMemory)?;
                 // set valid
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
             ppn = pte.ppn();
         }
-        result
+        Ok(result.unwrap())
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// The leaf PTE covering `vpn` and the size of the page it maps
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, PageSize)> {
         let idx = vpn.indexes();
         let mut ppn = self.level0_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
+        let mut result: Option<(&mut PageTableEntry, PageSize)> = None;
         for i in 0..3 {
             let pte = &mut ppn.get_pte_array()[idx[i]];
-            if i == 2 {
-                result = Some(pte);
+            if i == 2 || pte.is_leaf() {
+                result = Some((pte, PageSize::from_level(i)));
                 break;
             }
             if !pte.is_valid() {
@@ -121,15 +183,55 @@ impl PageTable {
         }
         result
     }
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.create_pte(vpn).unwrap();
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Result<(), OutOfMemory> {
+        self.map_huge(vpn, ppn, flags, PageSize::Size4K)
+    }
+    /// Map a page of `size`, `vpn` and `ppn` must both be aligned to it
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        size: PageSize,
+    ) -> Result<(), OutOfMemory> {
+        assert!(
+            vpn.0 % size.pages() == 0 && ppn.0 % size.pages() == 0,
+            "vpn {:?} -> ppn {:#x} is not aligned to {:?}",
+            vpn,
+            ppn.0,
+            size
+        );
+        let pte = self.create_pte(vpn, size)?;
         assert!(!pte.is_valid(), "vpn {:?} is invalid before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        Ok(())
     }
-    pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
-        assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
+    /// Remove the leaf covering `vpn`, returns the size of the page it mapped
+    pub fn unmap(&mut self, vpn: VirtPageNum) -> PageSize {
+        let (pte, size) = self.find_pte(vpn).unwrap();
+        assert!(
+            pte.is_valid() || pte.swap_slot().is_some(),
+            "vpn {:?} is invalid before unmapping",
+            vpn
+        );
         *pte = PageTableEntry::empty();
+        size
+    }
+    /// Replace the mapping of `vpn` by a reference to swap `slot`
+    pub fn swap_out(&mut self, vpn: VirtPageNum, slot: usize) {
+        let (pte, size) = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid() && size == PageSize::Size4K, "vpn {:?} cannot be swapped out", vpn);
+        *pte = PageTableEntry::swapped(slot, pte.flags());
+    }
+    /// Clear the A bit of `vpn`, returns whether it was set
+    pub fn test_and_clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        match self.find_pte(vpn) {
+            Some((pte, _)) if pte.is_valid() && pte.flags().contains(PTEFlags::A) => {
+                pte.bits &= !(PTEFlags::A.bits() as usize);
+                true
+            }
+            _ => false,
+        }
     }
     pub fn from_token(satp: usize) -> Self {
         Self {
@@ -137,12 +239,20 @@ impl PageTable {
             frames: Vec::new(),
         }
     }
+    /// The valid PTE mapping `vpn`, `None` for pages out on swap as well;
+    /// inside a huge page it is narrowed to the 4K page of `vpn`
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn)
-            .map(|pte| { pte.clone() })
+        self.find_pte(vpn).filter(|(pte, _)| pte.is_valid()).map(|(pte, size)| {
+            if size == PageSize::Size4K {
+                pte.clone()
+            } else {
+                let ppn = PhysPageNum(pte.ppn().0 + vpn.0 % size.pages());
+                PageTableEntry::new(ppn, pte.flags())
+            }
+        })
     }
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate(va.clone().floor()).map(|pte| {
             let aligned_pa: PhysAddr = pte.ppn().into();
             let offset = va.page_offset();
             let aligned_pa_usize: usize = aligned_pa.into();
@@ -153,53 +263,3 @@ impl PageTable {
         8usize << 60 | self.level0_ppn.0
     }
 }
-
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
-    }
-    v
-}
-
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
-        }
-    }
-    string
-}
-
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
-}
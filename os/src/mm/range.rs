@@ -24,6 +24,9 @@ impl<T> Range<T>
     pub fn get_end(&self) -> T {
         self.end
     }
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
 }
 
 pub struct RangeIterator<T>
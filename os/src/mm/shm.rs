@@ -0,0 +1,131 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use crate::config::{PAGE_SIZE, SHMALL, SHMMAX};
+use crate::mm::address::PhysPageNum;
+use crate::mm::frame_allocator::{frame_alloc, within_memory, without_oom_kill, FrameTracker, OutOfMemory};
+use crate::sync::up::UPSafeCell;
+
+// System V style shared memory: a segment is a set of frames that several
+// address spaces map as `MapType::Shared` areas. Every attached area and the
+// segment table hold a reference, the frames are freed with the last one.
+
+/// `key` that always creates a new segment
+pub const IPC_PRIVATE: usize = 0;
+/// create the segment if the key has none
+pub const IPC_CREAT: usize = 0o1000;
+/// with IPC_CREAT, fail if the key already has a segment
+pub const IPC_EXCL: usize = 0o2000;
+/// `shmctl` command removing the segment id, attached mappings stay valid
+pub const IPC_RMID: usize = 0;
+
+// pages of all segments, removed ones included until their last mapping goes
+static SHM_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct ShmSegment {
+    frames: Vec<FrameTracker>,
+}
+
+impl ShmSegment {
+    fn new(pages: usize) -> Result<Self, OutOfMemory> {
+        let frames = (0..pages)
+            .map(|_| frame_alloc().ok_or(OutOfMemory))
+            .collect::<Result<Vec<_>, _>>()?;
+        SHM_PAGES.fetch_add(pages, Ordering::Relaxed);
+        Ok(Self { frames })
+    }
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+    /// frame backing page `index` of the segment
+    pub fn ppn(&self, index: usize) -> PhysPageNum {
+        self.frames[index].ppn
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        SHM_PAGES.fetch_sub(self.frames.len(), Ordering::Relaxed);
+    }
+}
+
+pub enum ShmError {
+    /// no segment for the key and IPC_CREAT not given, or no such id
+    NotFound,
+    /// the key has a segment and IPC_CREAT | IPC_EXCL was given
+    Exists,
+    /// size is 0, larger than SHMMAX or than the existing segment
+    Invalid,
+    /// the new segment would take the segments past SHMALL pages
+    NoSpace,
+    OutOfMemory,
+}
+
+pub struct ShmTable {
+    // id -> segment
+    segments: BTreeMap<usize, Arc<ShmSegment>>,
+    // key -> id, private segments have no key
+    keys: BTreeMap<usize, usize>,
+    next_id: usize,
+}
+
+impl ShmTable {
+    fn new() -> Self {
+        Self {
+            segments: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+    /// Look up the segment of `key`, or create one of `size` bytes, returns its id
+    pub fn get(&mut self, key: usize, size: usize, flags: usize) -> Result<usize, ShmError> {
+        if key != IPC_PRIVATE {
+            if let Some(&id) = self.keys.get(&key) {
+                if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                    return Err(ShmError::Exists);
+                }
+                if size > self.segments[&id].pages() * PAGE_SIZE {
+                    return Err(ShmError::Invalid);
+                }
+                return Ok(id);
+            }
+            if flags & IPC_CREAT == 0 {
+                return Err(ShmError::NotFound);
+            }
+        }
+        // limits are checked before any frame is taken
+        if size == 0 || size > SHMMAX {
+            return Err(ShmError::Invalid);
+        }
+        let pages = size.div_ceil(PAGE_SIZE);
+        if SHM_PAGES.load(Ordering::Relaxed) + pages > SHMALL {
+            return Err(ShmError::NoSpace);
+        }
+        if !within_memory(pages) {
+            return Err(ShmError::OutOfMemory);
+        }
+        let segment = without_oom_kill(|| ShmSegment::new(pages)).map_err(|_| ShmError::OutOfMemory)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.segments.insert(id, Arc::new(segment));
+        if key != IPC_PRIVATE {
+            self.keys.insert(key, id);
+        }
+        Ok(id)
+    }
+    pub fn segment(&self, id: usize) -> Option<Arc<ShmSegment>> {
+        self.segments.get(&id).cloned()
+    }
+    /// Forget `id`, the frames go away once the last mapping is detached
+    pub fn remove(&mut self, id: usize) -> Result<(), ShmError> {
+        self.segments.remove(&id).ok_or(ShmError::NotFound)?;
+        self.keys.retain(|_, v| *v != id);
+        Ok(())
+    }
+}
+
+lazy_static! {
+    pub static ref SHM_TABLE: UPSafeCell<ShmTable> = UPSafeCell::new(ShmTable::new());
+}
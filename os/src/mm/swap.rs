@@ -0,0 +1,159 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+use log::{info, warn};
+use crate::config::{PAGE_SIZE, VIRTIO0};
+use crate::io::virtio_blk::{VirtIOBlk, SECTOR_SIZE};
+use crate::mm::address::{PhysPageNum, VirtAddr, VirtPageNum};
+use crate::sync::up::UPSafeCell;
+use crate::task::manager::try_all_tasks;
+use crate::task::processor::curr_task;
+
+// Swap space on the virtio block device: the disk is cut into page-sized slots.
+// Evicted user pages keep their slot number in the non-present PTE.
+
+const SECTORS_PER_SLOT: usize = PAGE_SIZE / SECTOR_SIZE;
+
+pub struct SwapSpace {
+    device: VirtIOBlk,
+    // one bit per slot, set if in use
+    bitmap: Vec<u64>,
+    slots: usize,
+}
+
+impl SwapSpace {
+    fn new(device: VirtIOBlk) -> Self {
+        let slots = device.capacity() / SECTORS_PER_SLOT;
+        info!("{} swap slots on the block device", slots);
+        Self {
+            device,
+            bitmap: vec![0; (slots + 63) / 64],
+            slots,
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        let (word, bits) = self.bitmap.iter_mut().enumerate().find(|(_, bits)| **bits != u64::MAX)?;
+        let slot = word * 64 + bits.trailing_ones() as usize;
+        if slot >= self.slots {
+            return None;
+        }
+        *bits |= 1 << (slot % 64);
+        Some(slot)
+    }
+    fn dealloc(&mut self, slot: usize) {
+        let bits = &mut self.bitmap[slot / 64];
+        assert!(*bits & 1 << (slot % 64) != 0, "Swap slot {} has not been allocated!", slot);
+        *bits &= !(1 << (slot % 64));
+    }
+}
+
+lazy_static! {
+    // `None` when QEMU was started without a swap disk
+    pub static ref SWAP_SPACE: UPSafeCell<Option<SwapSpace>> =
+        UPSafeCell::new(VirtIOBlk::probe(VIRTIO0).map(SwapSpace::new));
+}
+
+/// A slot of swap space holding one evicted page, freed on drop
+pub struct SwapSlot {
+    pub id: usize,
+}
+
+impl SwapSlot {
+    /// Copy the page in `ppn` to the slot
+    pub fn write(&self, ppn: PhysPageNum) {
+        let mut swap = SWAP_SPACE.exclusive_access();
+        let swap = swap.as_mut().unwrap();
+        swap.device.write_blocks(self.id * SECTORS_PER_SLOT, ppn.get_bytes_array());
+    }
+    /// Copy the slot back into the page in `ppn`
+    pub fn read(&self, ppn: PhysPageNum) {
+        let mut swap = SWAP_SPACE.exclusive_access();
+        let swap = swap.as_mut().unwrap();
+        swap.device.read_blocks(self.id * SECTORS_PER_SLOT, ppn.get_bytes_array());
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        if let Some(swap) = SWAP_SPACE.exclusive_access().as_mut() {
+            swap.dealloc(self.id);
+        }
+    }
+}
+
+// Probe the swap disk now, its queue memory cannot be allocated once frames run out.
+pub fn init_swap() {
+    if SWAP_SPACE.exclusive_access().is_none() {
+        warn!("no block device, swapping disabled");
+    }
+}
+
+/// (slots in use, total slots), both 0 without a swap disk
+pub fn swap_stats() -> (usize, usize) {
+    SWAP_SPACE.exclusive_access().as_ref().map_or((0, 0), |swap| {
+        let used = swap.bitmap.iter().map(|bits| bits.count_ones() as usize).sum();
+        (used, swap.slots)
+    })
+}
+
+pub fn swap_alloc() -> Option<SwapSlot> {
+    SWAP_SPACE
+        .exclusive_access()
+        .as_mut()
+        .and_then(|swap| swap.alloc())
+        .map(|id| SwapSlot { id })
+}
+
+/// Push one user page out to swap so that its frame can be reused.
+///
+/// Tasks are scanned in turn and each one's pages with a clock: a page whose A bit is set
+/// gets it cleared and a second chance. Pinned pages stay, and so do all pages of tasks
+/// whose control block is borrowed further up the stack; an address space growing while
+/// borrowed makes room among its own pages, see `MemorySet::evict_one`. The kernel heap
+/// reclaims too, from wherever it runs out, so nothing is done if swap or the task table
+/// is borrowed there.
+pub fn reclaim() -> bool {
+    if !SWAP_SPACE.try_exclusive_access().is_some_and(|swap| swap.is_some()) {
+        return false;
+    }
+    let tasks = match try_all_tasks() {
+        Some(tasks) => tasks,
+        None => return false,
+    };
+    // the first pass may only clear A bits
+    for _ in 0..2 {
+        for task in tasks.iter() {
+            if let Some(mut inner) = task.try_inner_exclusive_access() {
+                if inner.usr_mem.evict_one() {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Keep page `vpn` of the running task resident until `unpin_current`, it is brought
+/// back first if it is out on swap. Syscalls pin the user memory they work on, so that
+/// making room for another page cannot take it away.
+pub fn pin_current(vpn: VirtPageNum) {
+    if let Some(task) = curr_task() {
+        task.inner_exclusive_access().usr_mem.pin(vpn);
+    }
+}
+
+/// Release the pages the running task's last syscall pinned
+pub fn unpin_current() {
+    if let Some(task) = curr_task() {
+        task.inner_exclusive_access().usr_mem.unpin_all();
+    }
+}
+
+/// Bring back the page at `va` of the running task if it was swapped out,
+/// returns false if `va` is not a swapped page
+pub fn swap_in_current(va: usize) -> bool {
+    match curr_task() {
+        Some(task) => task.inner_exclusive_access().usr_mem.swap_in(VirtAddr::from(va).floor()),
+        None => false,
+    }
+}
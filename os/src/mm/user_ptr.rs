@@ -0,0 +1,161 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::mm::address::{PhysPageNum, VirtAddr, VirtPageNum};
+use crate::mm::page_cache::copy_on_write_current;
+use crate::mm::page_table::PageTable;
+use crate::mm::swap::pin_current;
+use crate::syscall::Errno;
+
+// Checked access to user memory named by syscall arguments. Every page has to
+// lie in the user half, be mapped with U and R, and with W to be written to,
+// anything else is EFAULT. Pages are pinned until the syscall returns, and
+// brought back first if they are out on swap, so the token must be the running
+// task's and its TCB must not be borrowed.
+
+/// The frame of user page `vpn`, if the user may read it (and write it if `write`)
+fn user_page(page_table: &PageTable, vpn: VirtPageNum, write: bool) -> Result<PhysPageNum, Errno> {
+    pin_current(vpn);
+    let mut pte = page_table.translate(vpn).ok_or(Errno::EFAULT)?;
+    // a private file mapping is written through its own copy
+    if write && !pte.writable() && copy_on_write_current(VirtAddr::from(vpn).into()) {
+        pte = page_table.translate(vpn).ok_or(Errno::EFAULT)?;
+    }
+    if !pte.user() || !pte.readable() || (write && !pte.writable()) {
+        return Err(Errno::EFAULT);
+    }
+    Ok(pte.ppn())
+}
+
+/// `len` bytes of user memory at `ptr`
+pub struct UserSlice {
+    token: usize,
+    start: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(token: usize, ptr: *const u8, len: usize) -> Self {
+        Self {
+            token,
+            start: ptr as usize,
+            len,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// `len` bytes of the slice from `offset` on, cut at its end
+    pub fn sub(&self, offset: usize, len: usize) -> UserSlice {
+        let offset = offset.min(self.len);
+        UserSlice::new(self.token, (self.start + offset) as *const u8, len.min(self.len - offset))
+    }
+    /// The slice cut at page boundaries, as kernel slices of the frames
+    fn buffers(&self, write: bool) -> Result<Vec<&'static mut [u8]>, Errno> {
+        let end = self.start.checked_add(self.len).ok_or(Errno::EFAULT)?;
+        if end > USER_SPACE_END {
+            return Err(Errno::EFAULT);
+        }
+        let page_table = PageTable::from_token(self.token);
+        let mut buffers = Vec::new();
+        let mut start = self.start;
+        while start < end {
+            let start_va = VirtAddr::from(start);
+            let ppn = user_page(&page_table, start_va.floor(), write)?;
+            let offset = start_va.page_offset();
+            let chunk = (start - offset + PAGE_SIZE).min(end) - start;
+            buffers.push(&mut ppn.get_bytes_array()[offset..offset + chunk]);
+            start += chunk;
+        }
+        Ok(buffers)
+    }
+    pub fn read_buffers(&self) -> Result<Vec<&'static [u8]>, Errno> {
+        Ok(self.buffers(false)?.into_iter().map(|buffer| &*buffer).collect())
+    }
+    pub fn write_buffers(&self) -> Result<Vec<&'static mut [u8]>, Errno> {
+        self.buffers(true)
+    }
+    /// Copy `src` to the start of the slice, which must be at least as long
+    pub fn copy_out(&self, src: &[u8]) -> Result<(), Errno> {
+        let dst = UserSlice::new(self.token, self.start as *const u8, src.len().min(self.len));
+        let mut copied = 0;
+        for buffer in dst.write_buffers()? {
+            buffer.copy_from_slice(&src[copied..copied + buffer.len()]);
+            copied += buffer.len();
+        }
+        Ok(())
+    }
+    /// Fill `dst` from the start of the slice
+    pub fn copy_in(&self, dst: &mut [u8]) -> Result<(), Errno> {
+        let src = UserSlice::new(self.token, self.start as *const u8, dst.len().min(self.len));
+        let mut copied = 0;
+        for buffer in src.read_buffers()? {
+            dst[copied..copied + buffer.len()].copy_from_slice(buffer);
+            copied += buffer.len();
+        }
+        Ok(())
+    }
+}
+
+/// A `T` in user memory, copied bytewise so it may be unaligned or cross pages
+pub struct UserPtr<T> {
+    token: usize,
+    ptr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(token: usize, ptr: *mut T) -> Self {
+        Self {
+            token,
+            ptr: ptr as usize,
+            _marker: PhantomData,
+        }
+    }
+    pub fn is_null(&self) -> bool {
+        self.ptr == 0
+    }
+    fn bytes(&self) -> UserSlice {
+        UserSlice::new(self.token, self.ptr as *const u8, size_of::<T>())
+    }
+    pub fn read(&self) -> Result<T, Errno> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let dst = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        self.bytes().copy_in(dst)?;
+        Ok(unsafe { value.assume_init() })
+    }
+    pub fn write(&self, value: T) -> Result<(), Errno> {
+        let src = unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        self.bytes().copy_out(src)
+    }
+}
+
+/// The NUL-terminated string at `ptr`, ENAMETOOLONG if it has more than `max_len` bytes
+pub fn read_user_str(token: usize, ptr: *const u8, max_len: usize) -> Result<String, Errno> {
+    let page_table = PageTable::from_token(token);
+    let mut bytes = Vec::new();
+    let mut va = ptr as usize;
+    loop {
+        if va >= USER_SPACE_END {
+            return Err(Errno::EFAULT);
+        }
+        let ppn = user_page(&page_table, VirtAddr::from(va).floor(), false)?;
+        let page = &ppn.get_bytes_array()[VirtAddr::from(va).page_offset()..];
+        let end = page.iter().position(|&byte| byte == 0);
+        let chunk = &page[..end.unwrap_or(page.len())];
+        if bytes.len() + chunk.len() > max_len {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        bytes.extend_from_slice(chunk);
+        if end.is_some() {
+            break;
+        }
+        va += chunk.len();
+    }
+    String::from_utf8(bytes).map_err(|_| Errno::EINVAL)
+}
@@ -0,0 +1,37 @@
+use lazy_static::*;
+use riscv::register::time;
+use crate::sync::up::UPSafeCell;
+
+// Kernel entropy source: a xorshift64* generator that folds in the time
+// counter on every draw, so values depend on when user programs run.
+pub struct Entropy {
+    state: u64,
+}
+
+impl Entropy {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        Self { state: seed | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= (time::read() as u64).rotate_left(32);
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+lazy_static! {
+    static ref ENTROPY: UPSafeCell<Entropy> = UPSafeCell::new(Entropy::new(time::read() as u64));
+}
+
+pub fn random_u64() -> u64 {
+    ENTROPY.exclusive_access().next_u64()
+}
+
+// A random number of pages in [0, max_pages), used to slide user mappings
+pub fn random_pages(max_pages: usize) -> usize {
+    random_u64() as usize % max_pages
+}
@@ -0,0 +1,81 @@
+use crate::mm::frame_allocator::OutOfMemory;
+use crate::mm::memory_set::ElfError;
+use crate::mm::shm::ShmError;
+
+/// Error of a system call, handed to user space negated. The numbers are Linux's.
+#[allow(unused)]
+#[repr(isize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Errno {
+    /// not allowed, e.g. moving a session leader to another group
+    EPERM = 1,
+    /// no such file, app or IPC key
+    ENOENT = 2,
+    /// no such process
+    ESRCH = 3,
+    /// the arguments of exec do not fit in ARG_MAX
+    E2BIG = 7,
+    /// not an executable image
+    ENOEXEC = 8,
+    /// bad file descriptor
+    EBADF = 9,
+    /// no child process to wait for
+    ECHILD = 10,
+    /// try again
+    EAGAIN = 11,
+    ENOMEM = 12,
+    /// the file was not opened for what is asked of it
+    EACCES = 13,
+    /// a pointer argument is not valid user memory
+    EFAULT = 14,
+    EEXIST = 17,
+    /// the file can not be mapped
+    ENODEV = 19,
+    EINVAL = 22,
+    /// not a terminal
+    ENOTTY = 25,
+    /// the file is a program that may be running, it can not be written
+    ETXTBSY = 26,
+    /// no room left, e.g. for shared memory within SHMALL
+    ENOSPC = 28,
+    /// a path or name is longer than allowed
+    ENAMETOOLONG = 36,
+    /// no such system call
+    ENOSYS = 38,
+}
+
+impl Errno {
+    /// the value returned in a0
+    pub fn code(self) -> isize {
+        -(self as isize)
+    }
+}
+
+pub type SyscallResult = Result<usize, Errno>;
+
+impl From<OutOfMemory> for Errno {
+    fn from(_: OutOfMemory) -> Self {
+        Errno::ENOMEM
+    }
+}
+
+impl From<ElfError> for Errno {
+    fn from(err: ElfError) -> Self {
+        match err {
+            ElfError::OutOfMemory => Errno::ENOMEM,
+            _ => Errno::ENOEXEC,
+        }
+    }
+}
+
+impl From<ShmError> for Errno {
+    fn from(err: ShmError) -> Self {
+        match err {
+            ShmError::NotFound => Errno::ENOENT,
+            ShmError::Exists => Errno::EEXIST,
+            ShmError::Invalid => Errno::EINVAL,
+            ShmError::NoSpace => Errno::ENOSPC,
+            ShmError::OutOfMemory => Errno::ENOMEM,
+        }
+    }
+}
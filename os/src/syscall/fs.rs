@@ -1,54 +1,97 @@
-use crate::mm::page_table::translated_byte_buffer;
-use crate::print;
-use crate::io::console::Stdin;
-use crate::task::processor::current_user_satp;
-// use crate::task::suspend_and_run_next;
-
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
-
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            let mut buffers = translated_byte_buffer(current_user_satp(), buf, len);
-            buffers.iter_mut().for_each(|b| (**b)[0] = Stdin.getchar());
-            len as isize
-            // assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            // let mut c: usize;
-            // loop {
-            //     c = stdin().getchar() as usize;
-            //     if c == 0 {
-            //         suspend_and_run_next();
-            //         continue;
-            //     } else {
-            //         break;
-            //     }
-            // }
-            // let ch = c as u8;
-            // unsafe {
-            //     buffers[0].as_mut_ptr().write_volatile(ch);
-            // }
-            // 1
-        }
-        _ => {
-            panic!("[syscall] Unsupported fd in sys_read!");
-        }
+use alloc::sync::Arc;
+use core::mem::size_of;
+use crate::config::PATH_MAX;
+use crate::fs::{image_inode, Console, FileRef, InodeFile};
+use crate::mm::user_ptr::{read_user_str, UserPtr, UserSlice};
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::loader::get_app_data_by_name;
+use crate::task::processor::{curr_task, current_user_satp};
+
+// access modes of openat
+const O_ACCMODE: usize = 0o3;
+const O_RDONLY: usize = 0o0;
+const O_WRONLY: usize = 0o1;
+const O_RDWR: usize = 0o2;
+/// most buffers writev takes
+const IOV_MAX: usize = 1024;
+
+/// `struct iovec` of writev
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> SyscallResult {
+    let file = curr_task().unwrap().inner_exclusive_access().get_file(fd).ok_or(Errno::EBADF)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
     }
+    // the TCB is not borrowed, the buffer may have to be swapped in
+    file.read(UserSlice::new(current_user_satp(), buf, len))
 }
 
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SyscallResult {
+    let file = curr_task().unwrap().inner_exclusive_access().get_file(fd).ok_or(Errno::EBADF)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    file.write(UserSlice::new(current_user_satp(), buf, len))
+}
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_satp(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
-        }
+/// Write the `iovcnt` buffers described at `iov` one after another
+pub fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> SyscallResult {
+    if iovcnt > IOV_MAX {
+        return Err(Errno::EINVAL);
+    }
+    let file = curr_task().unwrap().inner_exclusive_access().get_file(fd).ok_or(Errno::EBADF)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    let token = current_user_satp();
+    let mut written = 0;
+    for i in 0..iovcnt {
+        let vec = UserPtr::new(token, (iov as usize + i * size_of::<IoVec>()) as *mut IoVec).read()?;
+        written += file.write(UserSlice::new(token, vec.base as *const u8, vec.len))?;
+    }
+    Ok(written)
+}
+
+/// Device request `cmd` on `fd`, the console takes the termios ones
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> SyscallResult {
+    let file = curr_task().unwrap().inner_exclusive_access().get_file(fd).ok_or(Errno::EBADF)?;
+    file.ioctl(cmd, arg)
+}
+
+/// The console can be opened as /dev/console or /dev/tty, the programs linked into
+/// the kernel as /bin/<name>, only for reading: their pages may be some process's
+/// text, writing them is ETXTBSY. `dirfd` and `mode` do not matter.
+pub fn sys_openat(_dirfd: isize, path: *const u8, flags: usize, _mode: usize) -> SyscallResult {
+    let path = read_user_str(current_user_satp(), path, PATH_MAX)?;
+    let (readable, writable) = match flags & O_ACCMODE {
+        O_RDONLY => (true, false),
+        O_WRONLY => (false, true),
+        O_RDWR => (true, true),
+        _ => return Err(Errno::EINVAL),
+    };
+    let file: FileRef = match path.as_str() {
+        "/dev/console" | "/dev/tty" => Arc::new(Console::new(readable, writable)),
         _ => {
-            panic!("Unsupported fd in sys_write!");
+            let name = path.strip_prefix("/bin/").ok_or(Errno::ENOENT)?;
+            let image = get_app_data_by_name(name).ok_or(Errno::ENOENT)?;
+            if writable {
+                return Err(Errno::ETXTBSY);
+            }
+            Arc::new(InodeFile::new(image_inode(image)))
         }
-    }
+    };
+    Ok(curr_task().unwrap().inner_exclusive_access().alloc_fd(file))
 }
 
+pub fn sys_close(fd: usize) -> SyscallResult {
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.fd_table.get_mut(fd).and_then(Option::take).ok_or(Errno::EBADF)?;
+    Ok(0)
+}
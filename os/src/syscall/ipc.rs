@@ -0,0 +1,47 @@
+use core::arch::asm;
+use crate::mm::address::VirtAddr;
+use crate::mm::memory_set::MapPermission;
+use crate::mm::shm::{IPC_RMID, SHM_TABLE};
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::processor::curr_task;
+
+/// shmat flag, map the segment read-only
+const SHM_RDONLY: usize = 0o10000;
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> SyscallResult {
+    Ok(SHM_TABLE.exclusive_access().get(key, size, flags)?)
+}
+
+/// Segments can only be removed, attached mappings keep them alive until detached
+pub fn sys_shmctl(id: usize, cmd: usize) -> SyscallResult {
+    if cmd != IPC_RMID {
+        return Err(Errno::EINVAL);
+    }
+    SHM_TABLE.exclusive_access().remove(id)?;
+    Ok(0)
+}
+
+/// The kernel picks the address, `addr` must be 0
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> SyscallResult {
+    if addr != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let segment = SHM_TABLE.exclusive_access().segment(id).ok_or(Errno::EINVAL)?;
+    let mut permission = MapPermission::R | MapPermission::U;
+    if flags & SHM_RDONLY == 0 {
+        permission |= MapPermission::W;
+    }
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    Ok(inner.usr_mem.attach_shm(segment, permission)?)
+}
+
+pub fn sys_shmdt(addr: usize) -> SyscallResult {
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.usr_mem.detach_shm(VirtAddr::from(addr)) {
+        return Err(Errno::EINVAL);
+    }
+    unsafe { asm!("sfence.vma") };
+    Ok(0)
+}
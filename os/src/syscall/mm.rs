@@ -0,0 +1,175 @@
+use core::arch::asm;
+use core::mem::size_of;
+use crate::config::{MMAP_BASE, PAGE_SIZE, USER_SPACE_END};
+use crate::heap::heap_stats;
+use crate::mm::address::VirtAddr;
+use crate::mm::frame_allocator::{frame_stats, within_memory, without_oom_kill};
+use crate::mm::map_area::FileMapping;
+use crate::mm::memory_set::MapPermission;
+use crate::mm::swap::swap_stats;
+use crate::mm::user_ptr::UserSlice;
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::processor::{curr_task, current_user_satp};
+
+// mmap protections and flags
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// Move the end of the heap to `brk`, returns the new end, or the current one
+/// if `brk` can not be used (so brk(0) asks for it)
+pub fn sys_brk(brk: usize) -> SyscallResult {
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let brk = without_oom_kill(|| inner.usr_mem.set_brk(brk));
+    // pages may have been unmapped
+    unsafe { asm!("sfence.vma") };
+    Ok(brk)
+}
+
+/// Anonymous mappings are private, files are mapped through the page cache: writes to
+/// a private mapping copy the page, a shared one maps the cached frames and needs a file
+/// open for writing to be writable, which programs never are. `addr` is a hint that is not followed unless MAP_FIXED is given, then the
+/// range must not be mapped yet (replacing mappings is not supported). Pages are at
+/// least readable, PROT_NONE is EINVAL.
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> SyscallResult {
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return Err(Errno::EINVAL),
+    };
+    if len == 0 || prot == 0 || (shared && flags & MAP_ANONYMOUS != 0) {
+        return Err(Errno::EINVAL);
+    }
+    let file = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        if offset % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let file = usize::try_from(fd)
+            .ok()
+            .and_then(|fd| curr_task().unwrap().inner_exclusive_access().get_file(fd))
+            .ok_or(Errno::EBADF)?;
+        let inode = file.inode().ok_or(Errno::ENODEV)?;
+        if !file.readable() || (shared && prot & PROT_WRITE != 0 && !file.writable()) {
+            return Err(Errno::EACCES);
+        }
+        Some(FileMapping { inode, first_page: offset / PAGE_SIZE, shared })
+    };
+    let mut permission = MapPermission::R | MapPermission::U;
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let pages = len.div_ceil(PAGE_SIZE);
+    if !within_memory(pages) {
+        return Err(Errno::ENOMEM);
+    }
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let start = if flags & MAP_FIXED != 0 {
+        if addr % PAGE_SIZE != 0 || addr >= USER_SPACE_END {
+            return Err(Errno::EINVAL);
+        }
+        let start = VirtAddr::from(addr).floor();
+        if !inner.usr_mem.range_free(start, pages) {
+            return Err(Errno::EINVAL);
+        }
+        start
+    } else {
+        inner
+            .usr_mem
+            .find_free(VirtAddr::from(MMAP_BASE).floor(), pages)
+            .ok_or(Errno::ENOMEM)?
+    };
+    let usr_mem = &mut inner.usr_mem;
+    without_oom_kill(|| match file {
+        Some(file) => usr_mem.map_file(start, pages, permission, file),
+        None => usr_mem.map_anonymous(start, pages, permission),
+    })?;
+    Ok(VirtAddr::from(start).into())
+}
+
+/// Mappings can only be unmapped whole, as they were mapped
+pub fn sys_munmap(addr: usize, len: usize) -> SyscallResult {
+    if addr % PAGE_SIZE != 0 || addr >= USER_SPACE_END || len == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = curr_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.usr_mem.unmap_mapping(VirtAddr::from(addr).floor(), len.div_ceil(PAGE_SIZE)) {
+        return Err(Errno::EINVAL);
+    }
+    unsafe { asm!("sfence.vma") };
+    Ok(0)
+}
+
+/// room for the slab size classes in `MemInfo`, `slab_caches` says how many are used
+const MEMINFO_SLAB_CACHES: usize = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SlabInfo {
+    pub object_size: usize,
+    pub objects_in_use: usize,
+    pub objects_total: usize,
+    pub slabs: usize,
+}
+
+/// What `sys_meminfo` reports, frames and slots in pages, the heap in bytes
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemInfo {
+    pub page_size: usize,
+    pub frames_total: usize,
+    pub frames_free: usize,
+    pub frames_largest_free: usize,
+    pub heap_total: usize,
+    pub heap_allocated: usize,
+    pub heap_requested: usize,
+    pub heap_largest_free: usize,
+    pub swap_total: usize,
+    pub swap_used: usize,
+    pub slab_caches: usize,
+    pub slabs: [SlabInfo; MEMINFO_SLAB_CACHES],
+}
+
+pub fn sys_meminfo(info: *mut MemInfo) -> SyscallResult {
+    let frames = frame_stats();
+    let (heap, caches) = heap_stats();
+    let (swap_used, swap_total) = swap_stats();
+    let mut meminfo = MemInfo {
+        page_size: PAGE_SIZE,
+        frames_total: frames.total,
+        frames_free: frames.free,
+        frames_largest_free: frames.largest_free,
+        heap_total: heap.total,
+        heap_allocated: heap.allocated,
+        heap_requested: heap.user,
+        heap_largest_free: heap.largest_free,
+        swap_total,
+        swap_used,
+        slab_caches: caches.len().min(MEMINFO_SLAB_CACHES),
+        ..Default::default()
+    };
+    for (slot, cache) in meminfo.slabs.iter_mut().zip(caches.iter()) {
+        *slot = SlabInfo {
+            object_size: cache.object_size,
+            objects_in_use: cache.objects_in_use,
+            objects_total: cache.objects_total,
+            slabs: cache.partial_slabs + cache.full_slabs + cache.empty_slabs,
+        };
+    }
+    // the struct may straddle a page boundary
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&meminfo as *const MemInfo as *const u8, size_of::<MemInfo>())
+    };
+    UserSlice::new(current_user_satp(), info as *const u8, size_of::<MemInfo>()).copy_out(bytes)?;
+    Ok(0)
+}
@@ -1,85 +1,237 @@
 use crate::task::{exit_and_run_next, suspend_and_run_next};
-use crate::task::manager::add_task;
+use crate::task::manager::{add_task, all_tasks, pid2task};
+use alloc::string::String;
 use alloc::sync::Arc;
-use crate::mm::page_table::{translated_refmut, translated_str};
-use crate::println;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use log::{debug, warn};
+use crate::config::{ARG_MAX, PATH_MAX};
+use crate::mm::user_ptr::{read_user_str, UserPtr};
 use crate::task::loader::get_app_data_by_name;
+use crate::syscall::{Errno, SyscallResult};
 use crate::task::processor::{curr_task, current_user_satp};
+use crate::task::task::TaskControlBlock;
+
+/// low byte of the clone flags, the signal the parent gets when the child exits
+const CSIGNAL: usize = 0xff;
+/// wait4 returns 0 instead of waiting if no child has exited
+const WNOHANG: usize = 1;
 
 pub fn sys_exit(exit_code: i32) -> ! {
     exit_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
-pub fn sys_yield() -> isize {
+/// Processes have a single thread, so this is exit
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    sys_exit(exit_code)
+}
+
+pub fn sys_yield() -> SyscallResult {
     suspend_and_run_next();
-    0
+    Ok(0)
+}
+
+pub fn sys_getpid() -> SyscallResult {
+    Ok(curr_task().unwrap().pid.0)
 }
 
-pub fn sys_getpid() -> isize {
-    curr_task().unwrap().pid.0 as isize
+/// 0 for initproc, which has no parent
+pub fn sys_getppid() -> SyscallResult {
+    let task = curr_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    Ok(inner.parent.as_ref().and_then(|parent| parent.upgrade()).map_or(0, |parent| parent.getpid()))
 }
 
-pub fn sys_fork() -> isize {
+/// Move process `pid` (0 for the caller) to group `pgid` (0 for a group of its own).
+/// Only the caller and its children can be moved, ESRCH otherwise. EPERM if the
+/// process leads a session or is in another one, or if group `pgid` is not in the
+/// caller's session. Whether a child has exec'd is not tracked, it can be moved anyway.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SyscallResult {
+    let task = curr_task().unwrap();
+    let pid = if pid == 0 { task.getpid() } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+    let inner = task.inner_exclusive_access();
+    let sid = inner.sid;
+    let target = if pid == task.getpid() {
+        task.clone()
+    } else {
+        inner.children.iter().find(|child| child.getpid() == pid).cloned().ok_or(Errno::ESRCH)?
+    };
+    drop(inner);
+    let target_sid = target.inner_exclusive_access().sid;
+    if target_sid == pid || target_sid != sid {
+        return Err(Errno::EPERM);
+    }
+    if pgid != pid
+        && !all_tasks().iter().any(|task| {
+            let inner = task.inner_exclusive_access();
+            inner.pgid == pgid && inner.sid == sid
+        })
+    {
+        return Err(Errno::EPERM);
+    }
+    target.inner_exclusive_access().pgid = pgid;
+    Ok(0)
+}
+
+/// Group of process `pid`, 0 for the caller
+pub fn sys_getpgid(pid: usize) -> SyscallResult {
+    let task = match pid {
+        0 => curr_task().unwrap(),
+        pid => pid2task(pid).ok_or(Errno::ESRCH)?,
+    };
+    let pgid = task.inner_exclusive_access().pgid;
+    Ok(pgid)
+}
+
+/// Start a new session and group led by the caller, returns its id.
+/// EPERM if the caller leads a group already.
+pub fn sys_setsid() -> SyscallResult {
+    let task = curr_task().unwrap();
+    let pid = task.getpid();
+    let mut inner = task.inner_exclusive_access();
+    if inner.pgid == pid {
+        return Err(Errno::EPERM);
+    }
+    inner.pgid = pid;
+    inner.sid = pid;
+    Ok(pid)
+}
+
+/// There are no threads to clear the tid of, returns the tid, which is the pid
+pub fn sys_set_tid_address(_tidptr: *mut i32) -> SyscallResult {
+    sys_getpid()
+}
+
+/// Only the fork flavour of clone: the child gets a copy of the address space,
+/// on `stack` if that is not 0. Sharing anything with the child (CLONE_VM,
+/// CLONE_THREAD, ...) is EINVAL, and so is CLONE_VFORK, the parent would not wait
+/// for the child to exec or exit. The tid and TLS arguments are ignored.
+pub fn sys_clone(flags: usize, stack: usize, _ptid: usize, _tls: usize, _ctid: usize) -> SyscallResult {
+    if flags & !CSIGNAL != 0 {
+        return Err(Errno::EINVAL);
+    }
     let curr_task = curr_task().unwrap();
-    let new_task = curr_task.fork();
+    let new_task = curr_task.fork()?;
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.regs[10] = 0;
+    if stack != 0 {
+        trap_cx.regs[2] = stack;
+    }
     // add new task to scheduler
     add_task(new_task);
-    println!("[syscall] fork a pid {} process", new_pid);
-    new_pid as isize
+    debug!("fork: pid {}", new_pid);
+    Ok(new_pid)
+}
+
+/// The strings of the null-terminated array `ptrs`, a null `ptrs` is an empty array.
+/// `room` is what is left of ARG_MAX, the strings and their pointers are taken from it.
+fn read_user_strs(token: usize, ptrs: *const usize, room: &mut usize) -> Result<Vec<String>, Errno> {
+    let mut strings = Vec::new();
+    if ptrs.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let ptr = UserPtr::new(token, (ptrs as usize + strings.len() * size_of::<usize>()) as *mut usize).read()?;
+        if ptr == 0 {
+            return Ok(strings);
+        }
+        let string = read_user_str(token, ptr as *const u8, ARG_MAX).map_err(|errno| match errno {
+            Errno::ENAMETOOLONG => Errno::E2BIG,
+            errno => errno,
+        })?;
+        *room = room
+            .checked_sub(string.len() + 1 + size_of::<usize>())
+            .ok_or(Errno::E2BIG)?;
+        strings.push(string);
+    }
 }
 
-pub fn sys_exec(path: *const u8) -> isize {
+/// Run the app named by the last component of `path` with `argv` and `envp`
+pub fn sys_execve(path: *const u8, argv: *const usize, envp: *const usize) -> SyscallResult {
     let token = current_user_satp();
-    let path = translated_str(token, path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        println!("[syscall] exec");
-        let task = curr_task().unwrap();
-        task.exec(data);
-        0
-    } else {
-        println!("[syscall] fail to exec {}", path);
-        -1
+    let path = read_user_str(token, path, PATH_MAX)?;
+    let mut room = ARG_MAX;
+    let args = read_user_strs(token, argv, &mut room)?;
+    let envs = read_user_strs(token, envp, &mut room)?;
+    // apps are looked up by file name
+    let name = path.rsplit('/').next().unwrap_or(path.as_str());
+    let data = get_app_data_by_name(name).ok_or(Errno::ENOENT)?;
+    debug!("exec {}", path);
+    let task = curr_task().unwrap();
+    task.exec(data, &args, &envs).map_err(|err| {
+        warn!("fail to exec {}: {:?}", path, err);
+        err
+    })?;
+    Ok(0)
+}
+
+/// Wait for child `pid` to exit, for any child if `pid` is -1, for a child in the
+/// caller's group if it is 0 and for one in group -`pid` if it is below -1.
+/// Its status goes to `wstatus` unless that is null, resource usage is not reported.
+/// ECHILD if there is no such child, with WNOHANG 0 if it has not exited yet.
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize, _rusage: usize) -> SyscallResult {
+    loop {
+        if let Some(found_pid) = reap_child(pid, wstatus)? {
+            return Ok(found_pid);
+        }
+        if options & WNOHANG != 0 {
+            return Ok(0);
+        }
+        suspend_and_run_next();
     }
 }
 
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Free a child matching `pid` that has exited and store its status in `wstatus`,
+/// None if none has exited yet. On EFAULT the child is not reaped.
+fn reap_child(pid: isize, wstatus: *mut i32) -> Result<Option<usize>, Errno> {
     let task = curr_task().unwrap();
     // find a child process
 
     // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
+    let inner = task.inner_exclusive_access();
+    let pgid = inner.pgid;
+    let matches = |child: &Arc<TaskControlBlock>| match pid {
+        -1 => true,
+        0 => child.inner_exclusive_access().pgid == pgid,
+        pid if pid < -1 => child.inner_exclusive_access().pgid == pid.unsigned_abs(),
+        pid => child.getpid() == pid as usize,
+    };
+    if !inner.children.iter().any(matches) {
+        return Err(Errno::ECHILD);
         // ---- release current PCB
     }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
+    let pair = inner.children.iter().find(|p| {
         // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+        matches(p) && p.inner_exclusive_access().is_zombie()
         // ++++ release child PCB
     });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.usr_mem.satp(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+    let child = match pair {
+        Some(child) => child,
+        None => return Ok(None),
+    };
+    let found_pid = child.getpid();
+    // ++++ temporarily access child TCB exclusively
+    let exit_code = child.inner_exclusive_access().exit_code;
+    // ++++ release child PCB
+    // writing the status may swap a page back in, which needs the TCB
+    let token = inner.usr_mem.satp();
+    drop(inner);
+    let wstatus = UserPtr::new(token, wstatus);
+    if !wstatus.is_null() {
+        // a normal exit, the code in bits 8..16
+        wstatus.write((exit_code & 0xff) << 8)?;
     }
+    let mut inner = task.inner_exclusive_access();
+    let idx = inner.children.iter().position(|p| p.getpid() == found_pid).unwrap();
+    let child = inner.children.remove(idx);
+    // confirm that child will be deallocated after removing from children list
+    assert_eq!(Arc::strong_count(&child), 1);
+    Ok(Some(found_pid))
     // ---- release current PCB lock automatically
 }
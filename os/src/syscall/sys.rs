@@ -0,0 +1,50 @@
+use alloc::vec::Vec;
+use crate::mm::user_ptr::{UserPtr, UserSlice};
+use crate::syscall::SyscallResult;
+use crate::task::loader::app_names;
+use crate::task::processor::current_user_satp;
+
+/// length of each field of `struct utsname`, with the NUL
+const UTSNAME_LEN: usize = 65;
+
+/// `struct utsname`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UtsName {
+    sysname: [u8; UTSNAME_LEN],
+    nodename: [u8; UTSNAME_LEN],
+    release: [u8; UTSNAME_LEN],
+    version: [u8; UTSNAME_LEN],
+    machine: [u8; UTSNAME_LEN],
+    domainname: [u8; UTSNAME_LEN],
+}
+
+fn utsname_field(value: &str) -> [u8; UTSNAME_LEN] {
+    let mut field = [0; UTSNAME_LEN];
+    field[..value.len()].copy_from_slice(value.as_bytes());
+    field
+}
+
+pub fn sys_uname(buf: *mut UtsName) -> SyscallResult {
+    let uts = UtsName {
+        sysname: utsname_field("ACore"),
+        nodename: utsname_field("acore"),
+        release: utsname_field(env!("CARGO_PKG_VERSION")),
+        version: utsname_field("ACore-2024"),
+        machine: utsname_field("riscv64"),
+        domainname: utsname_field("(none)"),
+    };
+    UserPtr::new(current_user_satp(), buf).write(uts)?;
+    Ok(0)
+}
+
+/// The names exec can run, each closed by a NUL. Copies what fits in `len` bytes
+/// and returns the size of the whole list.
+pub fn sys_app_names(buf: *mut u8, len: usize) -> SyscallResult {
+    let names: Vec<u8> = app_names()
+        .iter()
+        .flat_map(|name| name.bytes().chain(Some(0)))
+        .collect();
+    UserSlice::new(current_user_satp(), buf, len).copy_out(&names)?;
+    Ok(names.len())
+}
@@ -0,0 +1,41 @@
+use alloc::vec;
+use crate::config::LOG_BUFFER_SIZE;
+use crate::logging::LOG_BUFFER;
+use crate::mm::user_ptr::UserSlice;
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::processor::current_user_satp;
+
+// actions of syslog(2), as numbered by Linux
+/// copy the newest whole lines of the log buffer that fit in `len` bytes
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+/// like READ_ALL, then clear the buffer
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+/// bytes currently in the buffer: nothing consumes the log (there is no
+/// SYSLOG_ACTION_READ), so all of it counts as unread
+const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+/// capacity of the buffer
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> SyscallResult {
+    match action {
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            // copied out first, writing to user pages may swap in and log
+            let mut bytes = vec![0; len.min(LOG_BUFFER_SIZE)];
+            let count = LOG_BUFFER.exclusive_access().read(&mut bytes);
+            UserSlice::new(current_user_satp(), buf, count).copy_out(&bytes[..count])?;
+            // the log is kept if it could not be handed out
+            if action == SYSLOG_ACTION_READ_CLEAR {
+                LOG_BUFFER.exclusive_access().clear();
+            }
+            Ok(count)
+        }
+        SYSLOG_ACTION_CLEAR => {
+            LOG_BUFFER.exclusive_access().clear();
+            Ok(0)
+        }
+        SYSLOG_ACTION_SIZE_UNREAD => Ok(LOG_BUFFER.exclusive_access().len()),
+        SYSLOG_ACTION_SIZE_BUFFER => Ok(LOG_BUFFER.exclusive_access().capacity()),
+        _ => Err(Errno::EINVAL),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::mm::user_ptr::UserPtr;
+use crate::syscall::{Errno, SyscallResult};
+use crate::task::processor::current_user_satp;
+use crate::task::suspend_and_run_next;
+use crate::time::get_time_ns;
+
+// There is no real-time clock, every clock counts from boot
+const NSEC_PER_SEC: usize = 1_000_000_000;
+const NSEC_PER_USEC: usize = 1_000;
+
+/// `struct timeval`, both fields are signed longs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeVal {
+    sec: isize,
+    usec: isize,
+}
+
+/// `struct timespec`, both fields are signed longs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeSpec {
+    sec: isize,
+    nsec: isize,
+}
+
+impl TimeSpec {
+    fn now() -> Self {
+        let ns = get_time_ns();
+        Self {
+            sec: (ns / NSEC_PER_SEC) as isize,
+            nsec: (ns % NSEC_PER_SEC) as isize,
+        }
+    }
+}
+
+/// The time zone is not reported, `tz` is ignored
+pub fn sys_gettimeofday(tv: *mut TimeVal, _tz: usize) -> SyscallResult {
+    let now = TimeSpec::now();
+    UserPtr::new(current_user_satp(), tv).write(TimeVal {
+        sec: now.sec,
+        usec: now.nsec / NSEC_PER_USEC as isize,
+    })?;
+    Ok(0)
+}
+
+/// All clocks are the same, `clock_id` is ignored
+pub fn sys_clock_gettime(_clock_id: usize, tp: *mut TimeSpec) -> SyscallResult {
+    UserPtr::new(current_user_satp(), tp).write(TimeSpec::now())?;
+    Ok(0)
+}
+
+/// Yield until `req` has passed. Nothing interrupts the sleep, so `rem` is not written.
+pub fn sys_nanosleep(req: *const TimeSpec, _rem: *mut TimeSpec) -> SyscallResult {
+    let req = UserPtr::new(current_user_satp(), req as *mut TimeSpec).read()?;
+    let (sec, nsec) = match (usize::try_from(req.sec), usize::try_from(req.nsec)) {
+        (Ok(sec), Ok(nsec)) if nsec < NSEC_PER_SEC => (sec, nsec),
+        _ => return Err(Errno::EINVAL),
+    };
+    let deadline = get_time_ns().saturating_add(sec.saturating_mul(NSEC_PER_SEC).saturating_add(nsec));
+    while get_time_ns() < deadline {
+        suspend_and_run_next();
+    }
+    Ok(0)
+}
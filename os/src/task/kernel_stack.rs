@@ -1,6 +1,9 @@
+use riscv::register::satp;
 use crate::mm::address::VirtAddr;
 use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::mm::frame_allocator::OutOfMemory;
 use crate::mm::memory_set::{KERNEL_SPACE, MapPermission};
+use crate::mm::page_table::PageTable;
 use crate::task::pid::PidHandle;
 
 pub struct KernelStack {
@@ -8,15 +11,15 @@ pub struct KernelStack {
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    pub fn new(pid_handle: &PidHandle) -> Result<Self, OutOfMemory> {
         let pid = pid_handle.0;
         let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
         KERNEL_SPACE.exclusive_access().insert_framed_areas(
             kernel_stack_bottom.into(),
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
-        );
-        Self { pid: pid_handle.0 }
+        )?;
+        Ok(Self { pid: pid_handle.0 })
     }
     pub fn top(&self) -> VirtAddr {
         let (_, kernel_stack_top) = kernel_stack_position(self.pid);
@@ -50,8 +53,30 @@ impl Drop for KernelStack {
     }
 }
 
+/// Kernel stacks sit below the trampoline, each with an unmapped guard page
+/// under its bottom, so an overflow faults instead of running into the next stack
 pub fn kernel_stack_position(pid: usize) -> (usize, usize) {
     let top = TRAMPOLINE - pid * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
+
+/// The pid whose kernel stack has its guard page at `addr`.
+/// Reads the active page table directly, a fault may hit with KERNEL_SPACE borrowed.
+pub fn kernel_stack_overflowed(addr: usize) -> Option<usize> {
+    if addr >= TRAMPOLINE {
+        return None;
+    }
+    let pid = (TRAMPOLINE - 1 - addr) / (KERNEL_STACK_SIZE + PAGE_SIZE);
+    // far below the stacks the slot has no room for a whole stack
+    let bottom = (TRAMPOLINE - pid * (KERNEL_STACK_SIZE + PAGE_SIZE)).checked_sub(KERNEL_STACK_SIZE)?;
+    if addr >= bottom {
+        return None;
+    }
+    // only a guard page if the stack above it exists
+    let page_table = PageTable::from_token(satp::read().bits());
+    page_table
+        .translate(VirtAddr::from(bottom).floor())
+        .is_some_and(|pte| pte.is_valid())
+        .then_some(pid)
+}
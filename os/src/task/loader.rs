@@ -60,6 +60,11 @@ pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
         .map(get_app_data)
 }
 
+/// every name exec and openat can find
+pub fn app_names() -> &'static [&'static str] {
+    &APP_NAMES
+}
+
 pub fn list_apps() {
     println!("/**** APPS ****");
     for app in APP_NAMES.iter() {
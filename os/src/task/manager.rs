@@ -1,5 +1,6 @@
-use alloc::collections::VecDeque;
-use alloc::sync::Arc;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use lazy_static::*;
 use crate::sync::up::UPSafeCell;
 use crate::task::task::TaskControlBlock;
@@ -21,17 +22,49 @@ impl TaskManager {
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
         self.ready_tasks_queue.pop_front()
     }
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.ready_tasks_queue.retain(|t| !Arc::ptr_eq(t, task));
+    }
 }
 
 lazy_static! {
     pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
         unsafe { UPSafeCell::new(TaskManager::new()) };
+    // every task alive, whatever its state, so that page reclaim can find user pages
+    pub static ref PID2TCB: UPSafeCell<BTreeMap<usize, Weak<TaskControlBlock>>> =
+        UPSafeCell::new(BTreeMap::new());
+}
+
+pub fn insert_into_pid2task(task: &Arc<TaskControlBlock>) {
+    PID2TCB.exclusive_access().insert(task.getpid(), Arc::downgrade(task));
+}
+
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TCB.exclusive_access().get(&pid).and_then(Weak::upgrade)
+}
+
+// Tasks that still exist, entries of dropped ones are removed on the way.
+pub fn all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    let mut pid2task = PID2TCB.exclusive_access();
+    pid2task.retain(|_, task| task.strong_count() > 0);
+    pid2task.values().filter_map(Weak::upgrade).collect()
+}
+
+/// like `all_tasks`, but None while the table is borrowed further up the stack
+pub fn try_all_tasks() -> Option<Vec<Arc<TaskControlBlock>>> {
+    let mut pid2task = PID2TCB.try_exclusive_access()?;
+    pid2task.retain(|_, task| task.strong_count() > 0);
+    Some(pid2task.values().filter_map(Weak::upgrade).collect())
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.exclusive_access().add(task);
 }
 
+pub fn remove_task(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().remove(task);
+}
+
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
@@ -8,20 +8,20 @@
 // pid for user apps.
 mod context;
 mod pid;
-mod task;
+pub(crate) mod task;
 pub(crate) mod loader;
 pub(crate) mod manager;
 pub(crate) mod processor;
-mod kernel_stack;
+pub(crate) mod kernel_stack;
 mod switch;
 
 use alloc::sync::Arc;
 use lazy_static::lazy_static;
+use log::{info, warn};
 pub use context::TaskContext;
-use crate::println;
 use crate::task::loader::get_app_data_by_name;
-use crate::task::manager::add_task;
-use crate::task::processor::{schedule, take_current_task};
+use crate::task::manager::{add_task, all_tasks, insert_into_pid2task, remove_task};
+use crate::task::processor::{curr_task, schedule, take_current_task};
 use crate::task::task::{TaskControlBlock, TaskStatus};
 
 // Suspend the current 'Running' task and run the next task in task list.
@@ -53,8 +53,8 @@ pub fn exit_and_run_next(exit_code: i32) {
 
     let pid = task.getpid();
     if pid == IDLE_PID {
-        println!(
-            "[kernel] Idle process exit with exit_code {} ...",
+        info!(
+            "idle process exit with exit_code {} ...",
             exit_code
         );
         if exit_code != 0 {
@@ -66,7 +66,18 @@ pub fn exit_and_run_next(exit_code: i32) {
         }
     }
 
-    // **** access current TCB exclusively
+    do_exit(&task, exit_code);
+    // drop task manually to maintain rc correctly
+    drop(task);
+    // we do not have to save task context
+    let mut _unused = TaskContext::init();
+    schedule(&mut _unused as *mut _);
+}
+
+// Turn `task` into a zombie: record the exit code, hand its children to initproc
+// and free its user space. The task must not be in the ready queue.
+fn do_exit(task: &Arc<TaskControlBlock>, exit_code: i32) {
+    // **** access TCB exclusively
     let mut inner = task.inner_exclusive_access();
     // Change status to Zombie
     inner.task_status = TaskStatus::Zombie;
@@ -74,7 +85,7 @@ pub fn exit_and_run_next(exit_code: i32) {
     inner.exit_code = exit_code;
     // do not move to its parent but under initproc
     // ++++++ access initproc TCB exclusively
-    {
+    if !inner.children.is_empty() {
         let mut initproc_inner = INITPROC.inner_exclusive_access();
         for child in inner.children.iter() {
             child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
@@ -86,21 +97,50 @@ pub fn exit_and_run_next(exit_code: i32) {
     inner.children.clear();
     // deallocate user space
     inner.usr_mem.clear();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
-    drop(task);
-    // we do not have to save task context
-    let mut _unused = TaskContext::init();
-    schedule(&mut _unused as *mut _);
+    // close open files
+    inner.fd_table.clear();
+    // **** release TCB
+}
+
+// Kill the user process holding the most frames, the last resort when swapping
+// cannot free one. The running task, initproc, and tasks whose control blocks
+// (or those of the ones reparented with them) are borrowed further up the stack
+// are never picked. Returns false if there was nothing to kill.
+pub fn oom_kill() -> bool {
+    let current = curr_task();
+    let victim = all_tasks()
+        .into_iter()
+        .filter(|task| task.getpid() != IDLE_PID)
+        .filter(|task| !current.as_ref().is_some_and(|current| Arc::ptr_eq(current, task)))
+        .filter_map(|task| {
+            let inner = task.try_inner_exclusive_access()?;
+            let reparent_ok = inner.children.is_empty()
+                || (INITPROC.try_inner_exclusive_access().is_some()
+                    && inner.children.iter().all(|child| child.try_inner_exclusive_access().is_some()));
+            let pages = inner.usr_mem.resident_pages();
+            drop(inner);
+            (reparent_ok && pages > 0).then_some((pages, task))
+        })
+        .max_by_key(|(pages, _)| *pages);
+    let (pages, task) = match victim {
+        Some(victim) => victim,
+        None => return false,
+    };
+    warn!("out of memory, killed process {} holding {} pages", task.getpid(), pages);
+    remove_task(&task);
+    // SIGKILL
+    do_exit(&task, -9);
+    true
 }
 
 lazy_static! {
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
-        get_app_data_by_name("initproc").unwrap()
-    ));
+    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(
+        TaskControlBlock::new(get_app_data_by_name("initproc").unwrap())
+            .expect("[kernel] initproc is not a valid ELF")
+    );
 }
 
 pub fn add_initproc() {
+    insert_into_pid2task(&INITPROC);
     add_task(INITPROC.clone());
 }
@@ -1,13 +1,17 @@
 use crate::trap::{trap_handler};
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::cell::RefMut;
+use log::trace;
 use crate::config::TRAP_CONTEXT;
+use crate::fs::{stdio, FileRef};
 use crate::mm::address::{PhysPageNum, VirtAddr};
-use crate::mm::memory_set::{KERNEL_SPACE, MemorySet};
-use crate::println;
+use crate::mm::frame_allocator::OutOfMemory;
+use crate::mm::memory_set::{ElfError, KERNEL_SPACE, MemorySet};
 use crate::sync::up::UPSafeCell;
 use crate::task::kernel_stack::KernelStack;
+use crate::task::manager::insert_into_pid2task;
 use crate::task::pid::{pid_alloc, PidHandle};
 use crate::task::TaskContext;
 use crate::trap::context::TrapContext;
@@ -30,6 +34,11 @@ pub struct TCBInner {
     pub parent: Option<Weak<TaskControlBlock>>,
     pub children: Vec<Arc<TaskControlBlock>>,
     pub exit_code: i32,
+    // process group and session, inherited on fork
+    pub pgid: usize,
+    pub sid: usize,
+    // indexed by fd, None for a closed fd
+    pub fd_table: Vec<Option<FileRef>>,
 }
 
 
@@ -37,19 +46,23 @@ impl TaskControlBlock {
     pub fn inner_exclusive_access(&self) -> RefMut<'_, TCBInner> {
         self.inner.exclusive_access()
     }
-    pub fn new(elf_data: &[u8]) -> Self {
+    pub fn try_inner_exclusive_access(&self) -> Option<RefMut<'_, TCBInner>> {
+        self.inner.try_exclusive_access()
+    }
+    pub fn new(elf_data: &'static [u8]) -> Result<Self, ElfError> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (user_mem, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (user_mem, user_sp, entry_point, tp) = MemorySet::from_elf(elf_data, &[], &[])?;
         let trap_cx_ppn = user_mem
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
-        println!("[trap] Map {:#x} -> {:#x}", TRAP_CONTEXT, trap_cx_ppn.0 << 12);
-        println!("[trap] User's sepc is {:#x}", entry_point);
+        trace!("trap context {:#x} -> {:#x}, entry {:#x}", TRAP_CONTEXT, trap_cx_ppn.0 << 12, entry_point);
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
         let kernel_stack_top = kernel_stack.get_top();
+        // the first process leads its own group and session
+        let pid = pid_handle.0;
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid_handle,
@@ -64,6 +77,9 @@ impl TaskControlBlock {
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
+                    pgid: pid,
+                    sid: pid,
+                    fd_table: stdio().into_iter().map(Some).collect(),
                 })
             },
         };
@@ -76,11 +92,14 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        trap_cx.set_tp(tp);
+        Ok(task_control_block)
     }
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// Replace the user space with `elf_data` started with `args` and `envs`,
+    /// the old one is kept if the image is rejected. Open files stay open.
+    pub fn exec(&self, elf_data: &'static [u8], args: &[String], envs: &[String]) -> Result<(), ElfError> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point, tp) = MemorySet::from_elf(elf_data, args, envs)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
@@ -103,20 +122,22 @@ impl TaskControlBlock {
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        trap_cx.set_tp(tp);
+        Ok(())
         // **** release inner automatically
     }
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    pub fn fork(self: &Arc<Self>) -> Result<Arc<Self>, OutOfMemory> {
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
         // copy user space(include trap context)
-        let memory_set = MemorySet::copy_from_user(&parent_inner.usr_mem);
+        let memory_set = MemorySet::copy_from_user(&parent_inner.usr_mem)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
         let kernel_stack_top = kernel_stack.get_top();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
@@ -131,17 +152,21 @@ impl TaskControlBlock {
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
+                    pgid: parent_inner.pgid,
+                    sid: parent_inner.sid,
+                    fd_table: parent_inner.fd_table.clone(),
                 })
             },
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
+        insert_into_pid2task(&task_control_block);
         // modify kernel_sp in trap_cx
         // **** access children PCB exclusively
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Ok(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
@@ -163,6 +188,22 @@ impl TCBInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+    /// Install `file` at the lowest free fd and return it
+    pub fn alloc_fd(&mut self, file: FileRef) -> usize {
+        match self.fd_table.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.fd_table[fd] = Some(file);
+                fd
+            }
+            None => {
+                self.fd_table.push(Some(file));
+                self.fd_table.len() - 1
+            }
+        }
+    }
+    pub fn get_file(&self, fd: usize) -> Option<FileRef> {
+        self.fd_table.get(fd).cloned().flatten()
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
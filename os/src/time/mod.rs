@@ -1,5 +1,5 @@
 use core::arch::global_asm;
-use crate::config::TIMER_INTERVAL;
+use crate::config::{CLOCK_FREQ, TIMER_INTERVAL};
 use riscv::register::{mhartid, mie, mscratch, mstatus, mtvec, time};
 
 global_asm!(include_str!("interrupt.s"));
@@ -18,6 +18,11 @@ pub fn get_time() -> usize {
     }
 }
 
+/// Nanoseconds since boot, read from the time CSR S mode may access
+pub fn get_time_ns() -> usize {
+    time::read() * (1_000_000_000 / CLOCK_FREQ)
+}
+
 #[link_section = ".bss.stack"]
 static mut TIMER_SCRATCH: [[usize; 5]; 8] = [[0; 5]; 8];
 
@@ -8,6 +8,16 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    // kernel tp (hart id), saved by __userret and restored by __usertrap
+    pub kernel_tp: usize,
+}
+
+/// Registers of the kernel at a trap taken in S mode, saved by __kerneltrap
+#[repr(C)]
+pub struct KernelTrapFrame {
+    pub regs: [usize; 32],
+    pub sstatus: usize,
+    pub sepc: usize,
 }
 
 impl TrapContext {
@@ -27,6 +37,7 @@ impl TrapContext {
             kernel_satp,  // addr of page table
             kernel_sp,    // kernel stack
             trap_handler, // addr of trap_handler function
+            kernel_tp: 0, // filled in by __userret
         };
         cx.set_sp(sp); // app's user stack pointer
         cx // return initial Trap Context of app
@@ -34,4 +45,8 @@ impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.regs[2] = sp;
     }
+    // thread pointer of the user program, the start of its TLS block
+    pub fn set_tp(&mut self, tp: usize) {
+        self.regs[4] = tp;
+    }
 }
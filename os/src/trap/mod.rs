@@ -1,9 +1,13 @@
 use core::arch::{asm, global_asm};
+use log::info;
 use riscv::register::mtvec::TrapMode;
 use riscv::register::{scause, sip, stval, stvec};
 
-use crate::{println, syscall::syscall};
+use crate::{print, println, syscall::syscall};
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::mm::page_cache::copy_on_write_current;
+use crate::mm::swap::{swap_in_current, unpin_current};
+use crate::task::kernel_stack::kernel_stack_overflowed;
 use crate::task::processor::{current_trap_cx, current_user_satp};
 use crate::task::{exit_and_run_next, suspend_and_run_next};
 
@@ -11,8 +15,18 @@ use crate::task::{exit_and_run_next, suspend_and_run_next};
 pub mod context;
 global_asm!(include_str!("trap.s"));
 
+use context::KernelTrapFrame;
+
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
 #[no_mangle]
 pub fn trap_handler() {
+    // from here on a trap is the kernel's own
+    set_kernel_trap_entry();
     let trap = scause::read().cause();
     let scause = scause::read();
     let stval = stval::read();
@@ -23,19 +37,29 @@ pub fn trap_handler() {
                 let mut cx = current_trap_cx();
                 cx.sepc += 4;
                 // get system call return value
-                let result = syscall(cx.regs[17], [cx.regs[10], cx.regs[11], cx.regs[12]]);
+                let mut args = [0; 6];
+                args.copy_from_slice(&cx.regs[10..16]);
+                let result = syscall(cx.regs[17], args);
+                unpin_current();
                 // cx is changed during sys_exec, so we have to call it again
                 cx = current_trap_cx();
                 cx.regs[10] = result as usize;
             }
+            // the page was pushed out to swap, bring it back and retry
+            scause::Exception::LoadPageFault
+            | scause::Exception::StorePageFault
+            | scause::Exception::InstructionPageFault
+                if swap_in_current(stval) => {}
+            // a write to a private file mapping, it gets its own copy of the page
+            scause::Exception::StorePageFault if copy_on_write_current(stval) => {}
             scause::Exception::LoadFault
             | scause::Exception::LoadPageFault
             | scause::Exception::StoreFault
             | scause::Exception::StorePageFault
             | scause::Exception::InstructionFault
             | scause::Exception::InstructionPageFault => {
-                println!(
-                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+                info!(
+                    "{:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
                     scause.cause(),
                     stval,
                     current_trap_cx().sepc,
@@ -44,16 +68,20 @@ pub fn trap_handler() {
                 exit_and_run_next(-2);
             }
             scause::Exception::IllegalInstruction => {
-                println!("[kernel] IllegalInstruction in application, kernel killed it.");
+                info!("IllegalInstruction in application, kernel killed it.");
                 // illegal instruction exit code
                 exit_and_run_next(-3);
             }
+            // ebreak and anything else a program can raise, only the program goes
             _ => {
-                panic!(
-                    "Unsupported exception {:?}, stval = {:#x}!",
+                info!(
+                    "{:?} in application, stval = {:#x}, sepc = {:#x}, kernel killed it.",
                     scause.cause(),
-                    stval
+                    stval,
+                    current_trap_cx().sepc,
                 );
+                // unsupported exception exit code
+                exit_and_run_next(-4);
             }
         },
         scause::Trap::Interrupt(intp) => match intp {
@@ -76,6 +104,40 @@ pub fn trap_handler() {
     trap_return();
 }
 
+/// Faults in kernel code end up here, they are bugs: report and panic
+#[no_mangle]
+pub fn kernel_trap_handler(frame: &KernelTrapFrame) -> ! {
+    let scause = scause::read();
+    let stval = stval::read();
+    let sp = frame.regs[2];
+    if let Some(pid) = kernel_stack_overflowed(stval).or_else(|| kernel_stack_overflowed(sp)) {
+        println!("[kernel] kernel stack of pid {} overflowed into its guard page", pid);
+    }
+    println!(
+        "[kernel] {:?} in kernel, sepc = {:#x}, stval = {:#x}, sstatus = {:#x}",
+        scause.cause(),
+        frame.sepc,
+        stval,
+        frame.sstatus
+    );
+    for (i, chunk) in frame.regs.chunks(4).enumerate() {
+        for (j, reg) in chunk.iter().enumerate() {
+            print!("{:>4} = {:#018x}  ", REG_NAMES[i * 4 + j], reg);
+        }
+        println!("");
+    }
+    panic!("{:?} in kernel at {:#x}", scause.cause(), frame.sepc);
+}
+
+pub fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kerneltrap();
+    }
+    unsafe {
+        stvec::write(__kerneltrap as usize, TrapMode::Direct);
+    }
+}
+
 fn set_user_trap_entry() {
     unsafe {
         stvec::write(TRAMPOLINE, TrapMode::Direct);
@@ -0,0 +1,466 @@
+//! A minimal dynamic loader (`/lib/ld.so`)
+//!
+//! The kernel maps it together with the program and relocates it, then jumps here
+//! with the initial stack the kernel laid out. We map the shared libraries the
+//! program needs from `/bin/<name>` with `openat` and `mmap`, resolve their symbols
+//! and jump to `_start`.
+
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+use core::panic::PanicInfo;
+
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_EXIT: usize = 93;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MMAP: usize = 222;
+
+const AT_FDCWD: isize = -100;
+const O_RDONLY: usize = 0;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+const PAGE_SIZE: usize = 4096;
+/// "/bin/" and the longest library name we look for
+const PATH_MAX: usize = 64;
+
+// auxiliary vector entries
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHNUM: usize = 5;
+const AT_ENTRY: usize = 9;
+
+// program header types and flags
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_PHDR: u32 = 6;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+// dynamic section tags
+const DT_NULL: i64 = 0;
+const DT_NEEDED: i64 = 1;
+const DT_PLTRELSZ: i64 = 2;
+const DT_HASH: i64 = 4;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_JMPREL: i64 = 23;
+
+// relocation types
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_64: u32 = 2;
+const R_RISCV_RELATIVE: u32 = 3;
+const R_RISCV_JUMP_SLOT: u32 = 5;
+
+const STB_WEAK: u8 = 2;
+
+/// the program plus every library it needs
+const MAX_OBJECTS: usize = 8;
+const MAX_NEEDED: usize = 8;
+
+#[repr(C)]
+struct ProgramHeader {
+    type_: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    file_size: u64,
+    mem_size: u64,
+    align: u64,
+}
+
+#[repr(C)]
+struct Dyn {
+    tag: i64,
+    val: u64,
+}
+
+#[repr(C)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+#[repr(C)]
+struct Sym {
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+/// A loaded image and the parts of its dynamic section we use
+#[derive(Clone, Copy)]
+struct Object {
+    base: usize,
+    strtab: usize,
+    symtab: usize,
+    sym_count: usize,
+    rela: usize,
+    rela_size: usize,
+    jmprel: usize,
+    jmprel_size: usize,
+    needed: [usize; MAX_NEEDED],
+    needed_count: usize,
+}
+
+impl Object {
+    const fn empty() -> Self {
+        Self {
+            base: 0,
+            strtab: 0,
+            symtab: 0,
+            sym_count: 0,
+            rela: 0,
+            rela_size: 0,
+            jmprel: 0,
+            jmprel_size: 0,
+            needed: [0; MAX_NEEDED],
+            needed_count: 0,
+        }
+    }
+
+    /// Read the dynamic section of an image loaded at `base`
+    unsafe fn load(base: usize, phdr: *const ProgramHeader, phnum: usize) -> Self {
+        let mut object = Self::empty();
+        object.base = base;
+        let dynamic = (0..phnum)
+            .map(|i| &*phdr.add(i))
+            .find(|ph| ph.type_ == PT_DYNAMIC);
+        let mut dyn_ptr = match dynamic {
+            Some(ph) => (base + ph.vaddr as usize) as *const Dyn,
+            // statically linked, nothing to do
+            None => return object,
+        };
+        while (*dyn_ptr).tag != DT_NULL {
+            let val = (*dyn_ptr).val as usize;
+            match (*dyn_ptr).tag {
+                DT_NEEDED => {
+                    if object.needed_count == MAX_NEEDED {
+                        fail("too many needed libraries");
+                    }
+                    object.needed[object.needed_count] = val;
+                    object.needed_count += 1;
+                }
+                DT_PLTRELSZ => object.jmprel_size = val,
+                // nchain, the second word of the table, is the number of symbols
+                DT_HASH => object.sym_count = *((base + val) as *const u32).add(1) as usize,
+                DT_STRTAB => object.strtab = base + val,
+                DT_SYMTAB => object.symtab = base + val,
+                DT_RELA => object.rela = base + val,
+                DT_RELASZ => object.rela_size = val,
+                DT_JMPREL => object.jmprel = base + val,
+                _ => {}
+            }
+            dyn_ptr = dyn_ptr.add(1);
+        }
+        object
+    }
+
+    /// Map the shared library `/bin/<name>` and read its dynamic section
+    unsafe fn map(name: *const u8) -> Self {
+        let fd = open_library(name);
+        // the ELF header, the program headers have to follow it in the first page
+        let header = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, fd, 0);
+        let phoff = *((header + 32) as *const u64) as usize;
+        let phnum = *((header + 56) as *const u16) as usize;
+        if phoff + phnum * core::mem::size_of::<ProgramHeader>() > PAGE_SIZE {
+            fail("program headers beyond the first page");
+        }
+        let phdrs = core::slice::from_raw_parts((header + phoff) as *const ProgramHeader, phnum);
+        let loads = || phdrs.iter().filter(|ph| ph.type_ == PT_LOAD);
+        let start = loads().map(|ph| page_floor(ph.vaddr as usize)).min();
+        let end = loads().map(|ph| page_ceil((ph.vaddr + ph.mem_size) as usize)).max();
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => fail("no loadable segment"),
+        };
+        // find a free range for the whole image, then map the segments into it
+        let span = mmap(0, end - start, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        syscall(SYSCALL_MUNMAP, [span, end - start, 0]);
+        let base = span - start;
+        for ph in loads() {
+            map_segment(fd, base, ph);
+        }
+        let object = Self::load(base, phdrs.as_ptr(), phnum);
+        syscall(SYSCALL_MUNMAP, [header, PAGE_SIZE, 0]);
+        syscall(SYSCALL_CLOSE, [fd as usize, 0, 0]);
+        object
+    }
+
+    unsafe fn sym(&self, index: usize) -> &Sym {
+        &*(self.symtab as *const Sym).add(index)
+    }
+
+    unsafe fn name(&self, offset: usize) -> *const u8 {
+        (self.strtab + offset) as *const u8
+    }
+
+    /// Address of the global symbol `name` defined by this object
+    unsafe fn lookup(&self, name: *const u8) -> Option<usize> {
+        (1..self.sym_count)
+            .map(|i| self.sym(i))
+            .find(|sym| sym.shndx != 0 && sym.info >> 4 != 0 && cstr_eq(self.name(sym.name as usize), name))
+            .map(|sym| self.base + sym.value as usize)
+    }
+}
+
+struct Loader {
+    objects: [Object; MAX_OBJECTS],
+    // file names of the libraries, the program has none
+    names: [*const u8; MAX_OBJECTS],
+    count: usize,
+}
+
+impl Loader {
+    /// Search the program first, then the libraries in load order
+    unsafe fn lookup(&self, name: *const u8) -> Option<usize> {
+        self.objects[..self.count].iter().find_map(|object| object.lookup(name))
+    }
+
+    /// Map every needed library, breadth first, skipping the ones already mapped
+    unsafe fn map_needed(&mut self) {
+        let mut next = 0;
+        while next < self.count {
+            let object = self.objects[next];
+            for i in 0..object.needed_count {
+                let name = object.name(object.needed[i]);
+                if self.names[1..self.count].iter().any(|&n| cstr_eq(n, name)) {
+                    continue;
+                }
+                if self.count == MAX_OBJECTS {
+                    fail("too many libraries");
+                }
+                self.objects[self.count] = Object::map(name);
+                self.names[self.count] = name;
+                self.count += 1;
+            }
+            next += 1;
+        }
+    }
+
+    unsafe fn relocate(&self, object: &Object, table: usize, size: usize) {
+        let relas = table as *const Rela;
+        for i in 0..size / core::mem::size_of::<Rela>() {
+            let rela = &*relas.add(i);
+            let addr = (object.base + rela.offset as usize) as *mut usize;
+            match rela.info as u32 {
+                R_RISCV_NONE => {}
+                R_RISCV_RELATIVE => *addr = object.base.wrapping_add(rela.addend as usize),
+                R_RISCV_64 | R_RISCV_JUMP_SLOT => {
+                    let index = (rela.info >> 32) as usize;
+                    let sym = object.sym(index);
+                    let value = if index == 0 {
+                        // no symbol, the addend is absolute
+                        0
+                    } else {
+                        match self.lookup(object.name(sym.name as usize)) {
+                            Some(value) => value,
+                            // undefined weak symbols resolve to 0
+                            None if sym.info >> 4 == STB_WEAK => 0,
+                            None => fail("undefined symbol"),
+                        }
+                    };
+                    *addr = value.wrapping_add(rela.addend as usize);
+                }
+                _ => fail("unsupported relocation"),
+            }
+        }
+    }
+}
+
+// keep the initial sp in s0, which is callee-saved, and restore it for the program
+global_asm!(
+    r#"
+    .section .text.entry
+    .globl _start
+_start:
+    mv s0, sp
+    mv a0, sp
+    call ld_main
+    mv sp, s0
+    jr a0
+"#
+);
+
+/// Load the program's libraries and return the address to jump to
+#[no_mangle]
+unsafe extern "C" fn ld_main(sp: *const usize) -> usize {
+    // skip argc, argv and envp to get to the auxiliary vector
+    let argc = *sp;
+    let mut auxv = sp.add(argc + 2);
+    while *auxv != 0 {
+        auxv = auxv.add(1);
+    }
+    auxv = auxv.add(1);
+    let (mut phdr, mut phnum, mut entry) = (0, 0, 0);
+    while *auxv != AT_NULL {
+        match *auxv {
+            AT_PHDR => phdr = *auxv.add(1),
+            AT_PHNUM => phnum = *auxv.add(1),
+            AT_ENTRY => entry = *auxv.add(1),
+            _ => {}
+        }
+        auxv = auxv.add(2);
+    }
+    let phdr = phdr as *const ProgramHeader;
+    // PT_PHDR gives the load bias of a PIE program, others are not moved
+    let bias = (0..phnum)
+        .map(|i| &*phdr.add(i))
+        .find(|ph| ph.type_ == PT_PHDR)
+        .map_or(0, |ph| phdr as usize - ph.vaddr as usize);
+    let mut loader = Loader {
+        objects: [Object::empty(); MAX_OBJECTS],
+        names: [core::ptr::null(); MAX_OBJECTS],
+        count: 1,
+    };
+    loader.objects[0] = Object::load(bias, phdr, phnum);
+    loader.map_needed();
+    for object in loader.objects[..loader.count].iter().rev() {
+        loader.relocate(object, object.rela, object.rela_size);
+        loader.relocate(object, object.jmprel, object.jmprel_size);
+    }
+    // with a shared usr_lib the runtime entry lives in the library
+    loader.lookup(c"_start".as_ptr().cast()).unwrap_or(entry)
+}
+
+/// Open `/bin/<name>` for reading
+unsafe fn open_library(name: *const u8) -> isize {
+    let mut path = [0u8; PATH_MAX];
+    path[..5].copy_from_slice(b"/bin/");
+    let mut len = 5;
+    while *name.add(len - 5) != 0 {
+        // keep the last byte for the terminating nul
+        if len == PATH_MAX - 1 {
+            fail("library name too long");
+        }
+        path[len] = *name.add(len - 5);
+        len += 1;
+    }
+    let fd = syscall(SYSCALL_OPENAT, [AT_FDCWD as usize, path.as_ptr() as usize, O_RDONLY]);
+    if fd < 0 {
+        fail("cannot open a needed library");
+    }
+    fd
+}
+
+/// Map a `PT_LOAD` segment privately at `base`, so our relocations copy the pages
+/// they write. The bss past the file part is zeroed, or anonymous from the next page.
+unsafe fn map_segment(fd: isize, base: usize, ph: &ProgramHeader) {
+    let mut prot = 0;
+    if ph.flags & PF_R != 0 {
+        prot |= PROT_READ;
+    }
+    if ph.flags & PF_W != 0 {
+        prot |= PROT_WRITE;
+    }
+    if ph.flags & PF_X != 0 {
+        prot |= PROT_EXEC;
+    }
+    let start = base + ph.vaddr as usize;
+    let file_end = start + ph.file_size as usize;
+    let mut mapped_end = page_floor(start);
+    if ph.file_size != 0 {
+        if ph.vaddr % PAGE_SIZE as u64 != ph.offset % PAGE_SIZE as u64 {
+            fail("segment not aligned to its file offset");
+        }
+        mmap(mapped_end, file_end - mapped_end, prot, MAP_PRIVATE | MAP_FIXED, fd, page_floor(ph.offset as usize));
+        mapped_end = page_ceil(file_end);
+    }
+    if ph.mem_size == ph.file_size {
+        return;
+    }
+    if ph.flags & PF_W == 0 {
+        fail("bss in a read-only segment");
+    }
+    if mapped_end > file_end {
+        core::ptr::write_bytes(file_end as *mut u8, 0, mapped_end - file_end);
+    }
+    let mem_end = page_ceil(start + ph.mem_size as usize);
+    if mem_end > mapped_end {
+        mmap(mapped_end, mem_end - mapped_end, prot, MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS, -1, 0);
+    }
+}
+
+fn page_floor(addr: usize) -> usize {
+    addr & !(PAGE_SIZE - 1)
+}
+
+fn page_ceil(addr: usize) -> usize {
+    page_floor(addr + PAGE_SIZE - 1)
+}
+
+unsafe fn cstr_eq(mut a: *const u8, mut b: *const u8) -> bool {
+    loop {
+        if *a != *b {
+            return false;
+        }
+        if *a == 0 {
+            return true;
+        }
+        a = a.add(1);
+        b = b.add(1);
+    }
+}
+
+fn syscall(id: usize, args: [usize; 3]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+        "ecall",
+        inlateout("x10") args[0] => ret,
+        in("x11") args[1],
+        in("x12") args[2],
+        in("x17") id
+        );
+    }
+    ret
+}
+
+/// `mmap`, failing the load when the kernel refuses
+fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> usize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+        "ecall",
+        inlateout("x10") addr => ret,
+        in("x11") len,
+        in("x12") prot,
+        in("x13") flags,
+        in("x14") fd,
+        in("x15") offset,
+        in("x17") SYSCALL_MMAP
+        );
+    }
+    if ret < 0 {
+        fail("cannot map a needed library");
+    }
+    ret as usize
+}
+
+fn fail(msg: &str) -> ! {
+    let prefix = "[ld.so] ";
+    syscall(SYSCALL_WRITE, [1, prefix.as_ptr() as usize, prefix.len()]);
+    syscall(SYSCALL_WRITE, [1, msg.as_ptr() as usize, msg.len()]);
+    syscall(SYSCALL_WRITE, [1, "\n".as_ptr() as usize, 1]);
+    syscall(SYSCALL_EXIT, [-1isize as usize, 0, 0]);
+    loop {}
+}
+
+#[panic_handler]
+fn panic_handler(_info: &PanicInfo) -> ! {
+    fail("panicked")
+}
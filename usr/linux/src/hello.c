@@ -0,0 +1,14 @@
+// Smallest program for the Linux ABI: built static against musl by `make linux`
+// in usr/, then embedded by the kernel as the app `hello`.
+#include <stdio.h>
+#include <time.h>
+
+int main(int argc, char *argv[]) {
+    printf("Hello from musl!\n");
+    for (int i = 0; i < argc; i++) {
+        printf("argv[%d] = %s\n", i, argv[i]);
+    }
+    struct timespec req = { .tv_sec = 0, .tv_nsec = 10 * 1000 * 1000 };
+    nanosleep(&req, NULL);
+    return 0;
+}
@@ -0,0 +1,61 @@
+//! The auxiliary vector the kernel leaves on the initial user stack
+
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_HWCAP: usize = 16;
+pub const AT_RANDOM: usize = 25;
+
+// every type we know is below this
+const AT_MAX: usize = 32;
+
+static mut AUXV: [Option<usize>; AT_MAX] = [None; AT_MAX];
+
+/// Record the auxv found above the initial `sp`: argc, argv, envp, then the (type, value) pairs
+pub(crate) unsafe fn init(sp: *const usize) {
+    let argc = *sp;
+    // skip argc, argv and its terminator
+    let mut ptr = sp.add(argc + 2);
+    // skip envp
+    while *ptr != 0 {
+        ptr = ptr.add(1);
+    }
+    ptr = ptr.add(1);
+    while *ptr != AT_NULL {
+        let (key, value) = (*ptr, *ptr.add(1));
+        if key < AT_MAX {
+            AUXV[key] = Some(value);
+        }
+        ptr = ptr.add(2);
+    }
+}
+
+/// Value of the auxv entry `key`, like getauxval(3) but `None` when it is missing
+pub fn getauxval(key: usize) -> Option<usize> {
+    if key < AT_MAX {
+        unsafe { AUXV[key] }
+    } else {
+        None
+    }
+}
+
+/// Read by the code the compiler inserts with `-Zstack-protector`
+#[no_mangle]
+pub static mut __stack_chk_guard: usize = 0;
+
+#[no_mangle]
+pub extern "C" fn __stack_chk_fail() -> ! {
+    panic!("[usr] stack smashing detected");
+}
+
+/// Seed the stack canary from the AT_RANDOM bytes
+pub(crate) unsafe fn init_stack_guard() {
+    if let Some(random) = getauxval(AT_RANDOM) {
+        // a zero low byte stops string functions from leaking or forging it
+        __stack_chk_guard = (random as *const usize).read_unaligned() & !0xff;
+    }
+}
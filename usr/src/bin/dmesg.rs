@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate usr_lib;
+
+use alloc::vec;
+use usr_lib::{syslog, write, SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_SIZE_BUFFER};
+
+const FD_STDOUT: usize = 1;
+
+// Print the kernel log buffer, oldest line first
+#[no_mangle]
+fn main() -> i32 {
+    let size = match syslog(SYSLOG_ACTION_SIZE_BUFFER, &mut []) {
+        Ok(size) => size,
+        Err(_) => return -1,
+    };
+    let mut buf = vec![0u8; size];
+    match syslog(SYSLOG_ACTION_READ_ALL, &mut buf) {
+        Ok(len) => {
+            let _ = write(FD_STDOUT, &buf[..len]);
+            0
+        }
+        Err(_) => -1,
+    }
+}
@@ -8,8 +8,8 @@ extern crate usr_lib;
 
 #[no_mangle]
 fn main() {
-    if fork() == 0 {
-        exec("hello_world\0");
+    if fork() == Ok(0) {
+        exec("hello_world", &["hello_world"]);
     } else {
         println!("!");
     }
@@ -8,7 +8,7 @@ extern crate usr_lib;
 
 #[no_mangle]
 fn main() {
-    if fork() == 0 {
+    if fork() == Ok(0) {
         println!("This is children!");
     } else {
         println!("This is parent!");
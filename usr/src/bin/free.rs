@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+use usr_lib::meminfo;
+
+#[macro_use]
+extern crate usr_lib;
+
+/// Percentage of `free` that is not in the largest free block
+fn fragmentation(free: usize, largest_free: usize) -> usize {
+    if free == 0 { 0 } else { 100 - largest_free * 100 / free }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let info = meminfo();
+    let kib = info.page_size / 1024;
+    let heap_free = info.heap_total - info.heap_allocated;
+    println!("{:>8} {:>10} {:>10} {:>10} {:>6}", "KiB", "total", "used", "free", "frag");
+    println!(
+        "{:>8} {:>10} {:>10} {:>10} {:>5}%",
+        "frames",
+        info.frames_total * kib,
+        (info.frames_total - info.frames_free) * kib,
+        info.frames_free * kib,
+        fragmentation(info.frames_free, info.frames_largest_free)
+    );
+    println!(
+        "{:>8} {:>10} {:>10} {:>10} {:>5}%",
+        "heap",
+        info.heap_total / 1024,
+        info.heap_allocated / 1024,
+        heap_free / 1024,
+        fragmentation(heap_free, info.heap_largest_free)
+    );
+    println!(
+        "{:>8} {:>10} {:>10} {:>10}",
+        "swap",
+        info.swap_total * kib,
+        info.swap_used * kib,
+        (info.swap_total - info.swap_used) * kib
+    );
+    println!("heap: {} bytes requested, {} handed out", info.heap_requested, info.heap_allocated);
+    println!("");
+    println!("{:>8} {:>10} {:>10} {:>10}", "slab", "in use", "objects", "slabs");
+    for slab in &info.slabs[..info.slab_caches] {
+        println!(
+            "{:>8} {:>10} {:>10} {:>10}",
+            slab.object_size, slab.objects_in_use, slab.objects_total, slab.slabs
+        );
+    }
+    0
+}
@@ -4,20 +4,25 @@
 #[macro_use]
 extern crate usr_lib;
 
-use usr_lib::{exec, fork, wait, yield_};
+use usr_lib::{exec, fork, wait, yield_, Errno};
 
 #[no_mangle]
 fn main() -> i32 {
-    if fork() == 0 {
-        exec("shell\0");
+    if fork() == Ok(0) {
+        let errno = exec("shell", &["shell"]);
+        println!("[initproc] cannot run the shell: {:?}", errno);
     } else {
         loop {
             let mut exit_code: i32 = 0;
-            let pid = wait(&mut exit_code);
-            if pid == -1 {
-                yield_();
-                continue;
-            }
+            let pid = match wait(&mut exit_code) {
+                Ok(pid) => pid,
+                // no children yet, orphans are handed over later
+                Err(Errno::ECHILD) => {
+                    yield_();
+                    continue;
+                }
+                Err(errno) => panic!("[initproc] wait failed: {:?}", errno),
+            };
             println!(
                 "[initproc] Released a zombie process, pid={}, exit_code={}",
                 pid, exit_code,
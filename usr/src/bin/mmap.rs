@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use usr_lib::{close, mmap, munmap, open, read, Errno, MAP_PRIVATE, MAP_SHARED, O_RDONLY, O_RDWR, PROT_READ, PROT_WRITE};
+
+#[macro_use]
+extern crate usr_lib;
+
+const PATH: &str = "/bin/hello_world";
+const PAGE_SIZE: usize = 4096;
+
+// The first byte of the file as read(2) sees it
+fn first_byte() -> u8 {
+    let fd = open(PATH, O_RDONLY).unwrap();
+    let mut byte = [0u8];
+    read(fd, &mut byte).unwrap();
+    close(fd).unwrap();
+    byte[0]
+}
+
+// Map a program file: a write to a private mapping copies the page and leaves the
+// file as it is, the file itself can not be written, nor mapped shared and writable.
+#[no_mangle]
+fn main() -> i32 {
+    let refused = open(PATH, O_RDWR) == Err(Errno::ETXTBSY);
+    println!("mmap: opening a program for writing is refused: {}", refused);
+    let fd = match open(PATH, O_RDONLY) {
+        Ok(fd) => fd,
+        Err(errno) => {
+            println!("mmap: open {} failed with {:?}", PATH, errno);
+            return -1;
+        }
+    };
+    let original = first_byte();
+
+    let private = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd as isize, 0).unwrap();
+    let page = private as *mut u8;
+    unsafe { page.write_volatile(!original) };
+    let copied = unsafe { page.read_volatile() } == !original && first_byte() == original;
+    munmap(private, PAGE_SIZE).unwrap();
+    println!("mmap: private write copied the page: {}", copied);
+
+    let shared_denied = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd as isize, 0) == Err(Errno::EACCES);
+    println!("mmap: writable shared mapping is refused: {}", shared_denied);
+    let shared = mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd as isize, 0).unwrap();
+    let same = unsafe { (shared as *const u8).read_volatile() } == original;
+    munmap(shared, PAGE_SIZE).unwrap();
+    println!("mmap: read-only shared mapping sees the file: {}", same);
+
+    close(fd).unwrap();
+    if refused && copied && shared_denied && same { 0 } else { -1 }
+}
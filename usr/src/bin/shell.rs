@@ -4,35 +4,149 @@
 
 extern crate alloc;
 
+use alloc::string::String;
+use alloc::vec::Vec;
+
 #[macro_use]
 extern crate usr_lib;
 
-use usr_lib::{exec, fork, waitpid};
+use usr_lib::line_editor::LineEditor;
+use usr_lib::{app_names, exec, fork, setpgid, waitpid, waitpid_options, Errno, WNOHANG};
+
+/// A command started with `&`, it runs in a process group of its own
+struct Job {
+    id: usize,
+    pid: usize,
+    command: String,
+}
 
 #[no_mangle]
 pub fn main() {
     println!("shell start!");
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut editor = LineEditor::new(app_names().unwrap_or_default());
     loop {
-        print!(">> ");
-        let str = usr_lib::console::Stdin::getshell();
-        if str.is_empty() {
-            println!(" ");
+        report_finished(&mut jobs);
+        let str = editor.read_line(">> ");
+        let (line, background) = match str.trim_end().strip_suffix('&') {
+            Some(line) => (line.trim_end(), true),
+            None => (str.trim_end(), false),
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
             continue;
         }
-        let fork_pid = fork();
-        if fork_pid == 0 {
-            if exec(str.as_str()) == -1 {
-                println!("[user] exec {} failed", str);
-                return;
+        match args[0] {
+            "jobs" => {
+                for job in jobs.iter() {
+                    println!("[{}] {} Running {} &", job.id, job.pid, job.command);
+                }
             }
-        } else {
-            let mut exit_code: i32 = 0;
-            waitpid(fork_pid as usize, &mut exit_code);
-            println!("[usr] process with pid {} exit with code {}", fork_pid, exit_code);
+            "fg" => match take_job(&mut jobs, args.get(1)) {
+                Some(job) => {
+                    println!("{}", job.command);
+                    wait_foreground(job.pid);
+                }
+                None => println!("fg: no such job"),
+            },
+            // a stub: there are no signals to stop a job with, so every job is running already
+            "bg" => match find_job(&jobs, args.get(1)) {
+                Some(job) => println!("bg: job {} already running, jobs cannot be stopped", job.id),
+                None => println!("bg: no such job"),
+            },
+            _ => {
+                let pid = match spawn(&args) {
+                    Some(pid) => pid,
+                    None => continue,
+                };
+                if background {
+                    let id = jobs.last().map_or(1, |job| job.id + 1);
+                    println!("[{}] {}", id, pid);
+                    jobs.push(Job {
+                        id,
+                        pid,
+                        command: String::from(line),
+                    });
+                } else {
+                    wait_foreground(pid);
+                }
+            }
+        }
+    }
+}
+
+/// Fork and exec `args` in a new process group, None if fork failed
+fn spawn(args: &[&str]) -> Option<usize> {
+    match fork() {
+        Ok(0) => {
+            // both sides set the group, whichever runs first
+            let _ = setpgid(0, 0);
+            let errno = exec(args[0], args);
+            println!("[user] exec {} failed: {:?}", args[0], errno);
+            usr_lib::exit(-4);
+            unreachable!();
+        }
+        Ok(pid) => {
+            let _ = setpgid(pid, pid);
+            Some(pid)
+        }
+        Err(errno) => {
+            println!("[usr] fork failed: {:?}", errno);
+            None
+        }
+    }
+}
+
+fn wait_foreground(pid: usize) {
+    let mut exit_code: i32 = 0;
+    if let Err(errno) = waitpid(pid, &mut exit_code) {
+        println!("[usr] waitpid {} failed: {:?}", pid, errno);
+        return;
+    }
+    println!("[usr] process with pid {} exit with code {}", pid, exit_code);
+}
+
+/// The job named by `spec`, `%n` or `n`, or the latest job without one
+fn find_job<'a>(jobs: &'a [Job], spec: Option<&&str>) -> Option<&'a Job> {
+    match spec {
+        None => jobs.last(),
+        Some(spec) => {
+            let id: usize = spec.trim_start_matches('%').parse().ok()?;
+            jobs.iter().find(|job| job.id == id)
         }
     }
 }
 
+fn take_job(jobs: &mut Vec<Job>, spec: Option<&&str>) -> Option<Job> {
+    let id = find_job(jobs, spec)?.id;
+    let index = jobs.iter().position(|job| job.id == id)?;
+    Some(jobs.remove(index))
+}
+
+/// Reap the background jobs that have exited and say so, before the next prompt
+fn report_finished(jobs: &mut Vec<Job>) {
+    jobs.retain(|job| {
+        let mut exit_code: i32 = 0;
+        match waitpid_options(-(job.pid as isize), &mut exit_code, WNOHANG) {
+            Ok(0) => true,
+            Ok(_) => {
+                if exit_code == 0 {
+                    println!("[{}] Done {}", job.id, job.command);
+                } else {
+                    println!("[{}] Exit {} {}", job.id, exit_code, job.command);
+                }
+                false
+            }
+            // reaped by someone else, nothing left to wait for
+            Err(Errno::ECHILD) => false,
+            Err(errno) => {
+                println!("[usr] waitpid {} failed: {:?}", job.pid, errno);
+                false
+            }
+        }
+    });
+}
+
 
 // let c = getchar();
 // match c {
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use usr_lib::{fork, shmat, shmctl, shmdt, shmget, waitpid, IPC_PRIVATE, IPC_RMID};
+
+#[macro_use]
+extern crate usr_lib;
+
+const ROUNDS: usize = 100;
+
+// Parent and child bump a counter in a shared page, the parent checks both
+// sets of increments landed in the same memory.
+#[no_mangle]
+fn main() -> i32 {
+    let id = match shmget(IPC_PRIVATE, 4096, 0) {
+        Ok(id) => id,
+        Err(errno) => {
+            println!("shm: shmget failed with {:?}", errno);
+            return -1;
+        }
+    };
+    let addr = match shmat(id, 0) {
+        Ok(addr) => addr,
+        Err(errno) => {
+            println!("shm: shmat failed with {:?}", errno);
+            return -1;
+        }
+    };
+    // attached mappings keep the segment alive
+    shmctl(id, IPC_RMID).unwrap();
+    let counter = unsafe { &*(addr as *const AtomicUsize) };
+    let pid = match fork() {
+        Ok(pid) => pid,
+        Err(errno) => {
+            println!("shm: fork failed with {:?}", errno);
+            return -1;
+        }
+    };
+    for _ in 0..ROUNDS {
+        // amoadd, a timer interrupt cannot split it
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    if pid == 0 {
+        return 0;
+    }
+    let mut exit_code = 0;
+    waitpid(pid, &mut exit_code).unwrap();
+    let total = counter.load(Ordering::Relaxed);
+    shmdt(addr).unwrap();
+    println!("shm: counter = {} (expected {})", total, 2 * ROUNDS);
+    if total == 2 * ROUNDS { 0 } else { -1 }
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+#[macro_use]
+extern crate usr_lib;
+
+use core::ptr::addr_of;
+
+#[thread_local]
+static mut COUNTER: usize = 41;
+
+#[thread_local]
+static mut ZEROED: usize = 0;
+
+#[no_mangle]
+fn main() -> i32 {
+    unsafe {
+        COUNTER += 1;
+        ZEROED += 1;
+        println!("tls: counter = {}, zeroed = {}", addr_of!(COUNTER).read(), addr_of!(ZEROED).read());
+    }
+    0
+}
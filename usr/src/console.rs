@@ -6,18 +6,38 @@ use crate::syscall::sys_write;
 const STDIN: usize = 0;
 const STDOUT: usize = 1;
 
-const BS: char = 8 as char;
-const DL: char = 127 as char;
-
 pub struct Stdin;
 
 pub struct Stdout;
 
 impl Stdin {
-    pub fn getchar() -> char {
+    fn getbyte() -> u8 {
         let mut c = [0u8; 1];
-        read(STDIN, &mut c);
-        c[0] as char
+        // stdin cannot fail, c stays 0 if it does
+        let _ = read(STDIN, &mut c);
+        c[0]
+    }
+
+    /// The next character, decoded from UTF-8. Bytes that do not form one read as U+FFFD.
+    pub fn getchar() -> char {
+        let first = Self::getbyte();
+        let len = match first.leading_ones() {
+            0 => return first as char,
+            len @ 2..=4 => len as usize,
+            _ => return char::REPLACEMENT_CHARACTER,
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for byte in bytes[1..len].iter_mut() {
+            *byte = Self::getbyte();
+            // not a continuation byte, it is dropped along with the bytes so far
+            if *byte & 0xc0 != 0x80 {
+                return char::REPLACEMENT_CHARACTER;
+            }
+        }
+        core::str::from_utf8(&bytes[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 
     pub fn getline() -> String {
@@ -31,22 +51,6 @@ impl Stdin {
         }
         str
     }
-
-    pub fn getshell() -> String {
-        let mut str = String::new();
-        let mut c = Self::getchar();
-        while c != '\n' && c != '\r' {
-            if c == DL || c == BS {
-                if !str.is_empty() {
-                    str.pop();
-                }
-            } else {
-                str.push(c);
-            }
-            c = Self::getchar();
-        }
-        str
-    }
 }
 
 impl Write for Stdout {
@@ -0,0 +1,62 @@
+//! Errors of system calls, the kernel returns them as negative values
+
+/// The numbers are Linux's, `Other` holds one the kernel should not return
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Errno {
+    EPERM,
+    ENOENT,
+    ESRCH,
+    E2BIG,
+    ENOEXEC,
+    EBADF,
+    ECHILD,
+    EAGAIN,
+    ENOMEM,
+    EACCES,
+    EFAULT,
+    EEXIST,
+    ENODEV,
+    EINVAL,
+    ENOTTY,
+    ETXTBSY,
+    ENOSPC,
+    ENAMETOOLONG,
+    ENOSYS,
+    Other(isize),
+}
+
+impl Errno {
+    pub fn from_code(code: isize) -> Self {
+        match -code {
+            1 => Errno::EPERM,
+            2 => Errno::ENOENT,
+            3 => Errno::ESRCH,
+            7 => Errno::E2BIG,
+            8 => Errno::ENOEXEC,
+            9 => Errno::EBADF,
+            10 => Errno::ECHILD,
+            11 => Errno::EAGAIN,
+            12 => Errno::ENOMEM,
+            13 => Errno::EACCES,
+            14 => Errno::EFAULT,
+            17 => Errno::EEXIST,
+            19 => Errno::ENODEV,
+            22 => Errno::EINVAL,
+            25 => Errno::ENOTTY,
+            26 => Errno::ETXTBSY,
+            28 => Errno::ENOSPC,
+            36 => Errno::ENAMETOOLONG,
+            38 => Errno::ENOSYS,
+            errno => Errno::Other(errno),
+        }
+    }
+}
+
+/// Split the return value of a system call into its value or its error
+pub(crate) fn decode(ret: isize) -> Result<usize, Errno> {
+    if ret < 0 {
+        Err(Errno::from_code(ret))
+    } else {
+        Ok(ret as usize)
+    }
+}
@@ -6,11 +6,22 @@ extern crate alloc;
 
 #[macro_use]
 pub mod console;
+pub mod auxv;
+pub mod errno;
+pub mod line_editor;
+pub mod termios;
 mod lang_items;
 mod syscall;
 mod config;
 
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::ptr::null;
 use syscall::*;
+use errno::decode;
+pub use errno::Errno;
 
 use heap::heap_allocator::*;
 use crate::config::{USER_HEAP_SIZE, USER_HEAP_UNIT};
@@ -20,10 +31,22 @@ static mut USER_HEAP: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 #[global_allocator]
 static HEAP: LockedBuddyHeap = unsafe { LockedBuddyHeap::new(USER_HEAP_UNIT) };
 
+// the kernel leaves argc, argv, envp and auxv at sp, hand it to Rust
+global_asm!(
+    r#"
+    .section .text.entry
+    .globl _start
+_start:
+    mv a0, sp
+    tail rust_start
+"#
+);
+
 #[no_mangle]
-#[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
+extern "C" fn rust_start(sp: *const usize) -> ! {
     unsafe {
+        auxv::init(sp);
+        auxv::init_stack_guard();
         let start = USER_HEAP.as_ptr() as usize;
         let end = start + USER_HEAP_SIZE;
         HEAP.add_to_heap(start, end);
@@ -38,10 +61,24 @@ fn main() -> i32 {
     panic!("[usr] Cannot find main!");
 }
 
-pub fn read(fd: usize, buf: &mut [u8]) -> isize { sys_read(fd, buf) }
+pub const O_RDONLY: usize = 0o0;
+pub const O_WRONLY: usize = 0o1;
+pub const O_RDWR: usize = 0o2;
+
+/// Open `path`, the console or one of the programs as /bin/<name>
+pub fn open(path: &str, flags: usize) -> Result<usize, Errno> {
+    let path: Vec<u8> = path.bytes().chain(Some(0)).collect();
+    decode(sys_openat(path.as_ptr(), flags))
+}
+
+pub fn close(fd: usize) -> Result<(), Errno> {
+    decode(sys_close(fd)).map(|_| ())
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize, Errno> { decode(sys_read(fd, buf)) }
 
-pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize, Errno> {
+    decode(sys_write(fd, buf))
 }
 
 pub fn exit(exit_code: i32) -> isize {
@@ -50,34 +87,175 @@ pub fn exit(exit_code: i32) -> isize {
 
 pub fn yield_() -> isize { sys_yield() }
 
-pub fn fork() -> isize {
-    sys_fork()
+pub fn getpid() -> usize {
+    sys_getpid() as usize
+}
+
+/// 0 for initproc
+pub fn getppid() -> usize {
+    sys_getppid() as usize
+}
+
+/// Move `pid` (0 for the caller) to group `pgid` (0 for a group led by `pid`)
+pub fn setpgid(pid: usize, pgid: usize) -> Result<(), Errno> {
+    decode(sys_setpgid(pid, pgid)).map(|_| ())
+}
+
+pub fn getpgid(pid: usize) -> Result<usize, Errno> {
+    decode(sys_getpgid(pid))
+}
+
+/// Start a new session led by the caller, returns its id
+pub fn setsid() -> Result<usize, Errno> {
+    decode(sys_setsid())
+}
+
+pub const SYSLOG_ACTION_READ_ALL: usize = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+pub const SYSLOG_ACTION_CLEAR: usize = 5;
+pub const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+pub const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+pub fn syslog(action: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+    decode(sys_syslog(action, buf))
+}
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const IPC_RMID: usize = 0;
+pub const SHM_RDONLY: usize = 0o10000;
+
+pub fn shmget(key: usize, size: usize, flags: usize) -> Result<usize, Errno> {
+    decode(sys_shmget(key, size, flags))
+}
+
+pub fn shmctl(id: usize, cmd: usize) -> Result<(), Errno> {
+    decode(sys_shmctl(id, cmd)).map(|_| ())
+}
+
+pub fn shmat(id: usize, flags: usize) -> Result<usize, Errno> {
+    decode(sys_shmat(id, flags))
 }
 
-pub fn exec(path: &str) -> isize {
-    sys_exec(path)
+pub fn shmdt(addr: usize) -> Result<(), Errno> {
+    decode(sys_shmdt(addr)).map(|_| ())
 }
 
-pub fn wait(exit_code: &mut i32) -> isize {
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Map `len` bytes of `fd` from `offset` on, or zeroed memory with MAP_ANONYMOUS (`fd` is
+/// then ignored), at an address the kernel picks unless MAP_FIXED is given
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> Result<usize, Errno> {
+    decode(sys_mmap(addr, len, prot, flags, fd, offset))
+}
+
+pub fn munmap(addr: usize, len: usize) -> Result<(), Errno> {
+    decode(sys_munmap(addr, len)).map(|_| ())
+}
+
+/// Room for the slab size classes, `MemInfo::slab_caches` of them are filled in
+pub const MEMINFO_SLAB_CACHES: usize = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SlabInfo {
+    pub object_size: usize,
+    pub objects_in_use: usize,
+    pub objects_total: usize,
+    pub slabs: usize,
+}
+
+/// Kernel memory statistics, frames and swap slots in pages, the heap in bytes
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemInfo {
+    pub page_size: usize,
+    pub frames_total: usize,
+    pub frames_free: usize,
+    pub frames_largest_free: usize,
+    pub heap_total: usize,
+    pub heap_allocated: usize,
+    pub heap_requested: usize,
+    pub heap_largest_free: usize,
+    pub swap_total: usize,
+    pub swap_used: usize,
+    pub slab_caches: usize,
+    pub slabs: [SlabInfo; MEMINFO_SLAB_CACHES],
+}
+
+pub fn meminfo() -> MemInfo {
+    let mut info = MemInfo::default();
+    sys_meminfo(&mut info as *mut _);
+    info
+}
+
+/// signal the parent gets when a forked child exits
+const SIGCHLD: usize = 17;
+/// option of wait4, return 0 instead of waiting for a child to exit
+pub const WNOHANG: usize = 1;
+
+/// Names of the programs exec can run
+pub fn app_names() -> Result<Vec<String>, Errno> {
+    let mut buf = vec![0u8; 256];
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                yield_();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
+        let len = decode(sys_app_names(&mut buf))?;
+        if len <= buf.len() {
+            buf.truncate(len);
+            break;
         }
+        buf.resize(len, 0);
     }
+    Ok(buf
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
 }
 
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                yield_();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
+/// The child's pid in the parent, 0 in the child
+pub fn fork() -> Result<usize, Errno> {
+    decode(sys_clone(SIGCHLD, 0))
+}
+
+/// Run `path` with arguments `args`, the first of which is the program name by convention.
+/// Only returns if `path` could not be run.
+pub fn exec(path: &str, args: &[&str]) -> Errno {
+    // the kernel takes NUL-terminated strings
+    let c_string = |s: &str| s.bytes().chain(Some(0)).collect::<Vec<u8>>();
+    let path = c_string(path);
+    let args: Vec<Vec<u8>> = args.iter().map(|arg| c_string(arg)).collect();
+    let argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).chain(Some(null())).collect();
+    let envp = [null()];
+    match decode(sys_execve(path.as_ptr(), argv.as_ptr(), envp.as_ptr())) {
+        Err(errno) => errno,
+        Ok(_) => unreachable!("exec returned without an error"),
+    }
+}
+
+/// Wait for any child to exit, ECHILD if there is none
+pub fn wait(exit_code: &mut i32) -> Result<usize, Errno> {
+    waitpid_options(-1, exit_code, 0)
+}
+
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> Result<usize, Errno> {
+    waitpid_options(pid as isize, exit_code, 0)
+}
+
+/// Like waitpid, with wait4 `options`. A `pid` of 0 waits for a child in the caller's
+/// group, below -1 for one in group -`pid`. With WNOHANG, Ok(0) if no child has exited.
+pub fn waitpid_options(pid: isize, exit_code: &mut i32, options: usize) -> Result<usize, Errno> {
+    let mut status = 0;
+    let pid = decode(sys_wait4(pid, &mut status, options))?;
+    if pid != 0 {
+        // the kernel reports the low byte of the exit code, the faults it kills with are negative
+        *exit_code = ((status >> 8) & 0xff) as i8 as i32;
     }
+    Ok(pid)
 }
\ No newline at end of file
@@ -0,0 +1,198 @@
+//! Reading a line from the console with cursor movement, history and completion
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::console::Stdin;
+use crate::termios::{tcgetattr, tcsetattr, ECHO};
+
+const STDIN: usize = 0;
+
+const CTRL_A: char = 1 as char;
+const CTRL_E: char = 5 as char;
+const BS: char = 8 as char;
+const TAB: char = '\t';
+const ESC: char = 27 as char;
+const DL: char = 127 as char;
+
+/// Keys sent as escape sequences
+enum Key {
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Delete,
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    // what Tab completes the first word with
+    completions: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn new(completions: Vec<String>) -> Self {
+        Self {
+            history: Vec::new(),
+            completions,
+        }
+    }
+
+    /// Print `prompt` and read a line, it goes to the history unless it is blank
+    pub fn read_line(&mut self, prompt: &str) -> String {
+        // the editor draws the line itself, the kernel must not echo it
+        let saved = tcgetattr(STDIN).ok();
+        if let Some(mut termios) = saved {
+            termios.lflag &= !ECHO;
+            let _ = tcsetattr(STDIN, &termios);
+        }
+        let line = self.edit(prompt);
+        if let Some(termios) = saved {
+            let _ = tcsetattr(STDIN, &termios);
+        }
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        line
+    }
+
+    fn edit(&self, prompt: &str) -> String {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // position in the history, history.len() is the line being typed, kept in `draft`
+        let mut index = self.history.len();
+        let mut draft: Vec<char> = Vec::new();
+        print!("{}", prompt);
+        loop {
+            match Stdin::getchar() {
+                '\r' | '\n' => {
+                    println!("");
+                    return line.into_iter().collect();
+                }
+                BS | DL => {
+                    if cursor > 0 {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                }
+                CTRL_A => cursor = 0,
+                CTRL_E => cursor = line.len(),
+                TAB => self.complete(&mut line, &mut cursor),
+                ESC => match read_escape() {
+                    Some(Key::Left) => cursor = cursor.saturating_sub(1),
+                    Some(Key::Right) => cursor = (cursor + 1).min(line.len()),
+                    Some(Key::Home) => cursor = 0,
+                    Some(Key::End) => cursor = line.len(),
+                    Some(Key::Delete) => {
+                        if cursor < line.len() {
+                            line.remove(cursor);
+                        }
+                    }
+                    Some(Key::Up) if index > 0 => {
+                        if index == self.history.len() {
+                            draft = line.clone();
+                        }
+                        index -= 1;
+                        line = self.history[index].chars().collect();
+                        cursor = line.len();
+                    }
+                    Some(Key::Down) if index < self.history.len() => {
+                        index += 1;
+                        line = match self.history.get(index) {
+                            Some(entry) => entry.chars().collect(),
+                            None => draft.clone(),
+                        };
+                        cursor = line.len();
+                    }
+                    _ => {}
+                },
+                c if !c.is_control() => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                _ => {}
+            }
+            redraw(prompt, &line, cursor);
+        }
+    }
+
+    /// Complete the first word when the cursor is at its end: with the only
+    /// match, or as far as all matches agree. Lists the matches if that adds nothing.
+    fn complete(&self, line: &mut Vec<char>, cursor: &mut usize) {
+        if line[..*cursor].contains(&' ') || line.get(*cursor).is_some_and(|c| *c != ' ') {
+            return;
+        }
+        let prefix: String = line[..*cursor].iter().collect();
+        let matches: Vec<&str> = self
+            .completions
+            .iter()
+            .map(String::as_str)
+            .filter(|name| name.starts_with(prefix.as_str()))
+            .collect();
+        let completion = match matches.as_slice() {
+            [] => return,
+            [name] => *name,
+            [first, rest @ ..] => {
+                let len = rest
+                    .iter()
+                    .map(|name| first.bytes().zip(name.bytes()).take_while(|(a, b)| a == b).count())
+                    .min()
+                    .unwrap();
+                &first[..len]
+            }
+        };
+        if completion.len() == prefix.len() && matches.len() > 1 {
+            println!("");
+            for name in matches {
+                print!("{}  ", name);
+            }
+            println!("");
+            return;
+        }
+        for c in completion[prefix.len()..].chars() {
+            line.insert(*cursor, c);
+            *cursor += 1;
+        }
+        // a complete name is followed by a space
+        if matches.len() == 1 && *cursor == line.len() {
+            line.push(' ');
+            *cursor += 1;
+        }
+    }
+}
+
+/// The key of the sequence after ESC, CSI (`ESC [`) or SS3 (`ESC O`), None for others
+fn read_escape() -> Option<Key> {
+    if !matches!(Stdin::getchar(), '[' | 'O') {
+        return None;
+    }
+    // parameters, e.g. `3` in `ESC [ 3 ~` or `1;5` for a key with Ctrl
+    let mut params = String::new();
+    let mut c = Stdin::getchar();
+    while c.is_ascii_digit() || c == ';' {
+        params.push(c);
+        c = Stdin::getchar();
+    }
+    let first = params.split(';').next().unwrap_or("");
+    match (c, first) {
+        ('A', _) => Some(Key::Up),
+        ('B', _) => Some(Key::Down),
+        ('C', _) => Some(Key::Right),
+        ('D', _) => Some(Key::Left),
+        ('H', _) | ('~', "1" | "7") => Some(Key::Home),
+        ('F', _) | ('~', "4" | "8") => Some(Key::End),
+        ('~', "3") => Some(Key::Delete),
+        _ => None,
+    }
+}
+
+/// Draw the line again and put the cursor back at `cursor`
+fn redraw(prompt: &str, line: &[char], cursor: usize) {
+    let text: String = line.iter().collect();
+    // from the start of the line, clearing what is left of the old text
+    print!("\r{}{}\x1b[K", prompt, text);
+    if cursor < line.len() {
+        print!("\x1b[{}D", line.len() - cursor);
+    }
+}
@@ -1,16 +1,40 @@
 use core::arch::asm;
+use crate::MemInfo;
 
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SYSLOG: usize = 116;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_SETSID: usize = 157;
 // const SYSCALL_GET_TIME: usize = 169;
-// const SYSCALL_GETPID: usize = 172;
-const SYSCALL_FORK: usize = 220;
-const SYSCALL_EXEC: usize = 221;
-const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_WAIT4: usize = 260;
+const SYSCALL_MEMINFO: usize = 1001;
+const SYSCALL_APP_NAMES: usize = 1002;
+
+/// openat relative to the working directory, paths are absolute anyway
+const AT_FDCWD: isize = -100;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
+    syscall6(id, [args[0], args[1], args[2], 0, 0, 0])
+}
+
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
     let mut ret: isize;
     unsafe {
         asm!(
@@ -18,12 +42,46 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
         inlateout("x10") args[0] => ret,
         in("x11") args[1],
         in("x12") args[2],
+        in("x13") args[3],
+        in("x14") args[4],
+        in("x15") args[5],
         in("x17") id
         );
     }
     ret
 }
 
+/// 功能：对设备进行控制，目前只有控制台支持 TCGETS/TCSETS，用于开关回显。
+///
+/// 参数：`fd` 为文件描述符，`cmd` 为请求号，`arg` 为请求的参数（通常是指针）。
+///
+/// 返回值：成功返回 0，否则返回负的错误码。
+///
+/// syscall ID：29
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+/// 功能：打开 `path` 处的文件，目前只有 /dev/console、/dev/tty 和内核中的程序 /bin/<name>。
+///
+/// 参数：`path` 以 \0 结尾，`flags` 的低两位为 O_RDONLY/O_WRONLY/O_RDWR，程序只能以 O_RDONLY 打开。
+///
+/// 返回值：成功返回新的文件描述符，否则返回负的错误码。
+///
+/// syscall ID：56
+pub fn sys_openat(path: *const u8, flags: usize) -> isize {
+    syscall(SYSCALL_OPENAT, [AT_FDCWD as usize, path as usize, flags])
+}
+
+/// 功能：关闭文件描述符 `fd`。
+///
+/// 返回值：成功返回 0，否则返回负的错误码。
+///
+/// syscall ID：57
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
 /// 功能：从文件中读取一段内容到缓冲区。
 ///
 /// 参数：fd 是待读取文件的文件描述符，切片 buffer 则给出缓冲区。
@@ -73,18 +131,162 @@ pub fn sys_yield() -> isize {
 //     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 // }
 
-// pub fn sys_getpid() -> isize {
-//     syscall(SYSCALL_GETPID, [0, 0, 0])
-// }
+/// 功能：获取当前进程的 pid。
+///
+/// syscall ID：172
+pub fn sys_getpid() -> isize {
+    syscall(SYSCALL_GETPID, [0, 0, 0])
+}
+
+/// 功能：获取父进程的 pid，没有父进程时返回 0。
+///
+/// syscall ID：173
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
+/// 功能：将进程 `pid`（0 表示当前进程）移入进程组 `pgid`（0 表示以 `pid` 为组号的新组）。
+///
+/// 返回值：成功返回 0，否则返回负的错误码。
+///
+/// syscall ID：154
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+/// 功能：获取进程 `pid`（0 表示当前进程）所在的进程组号。
+///
+/// syscall ID：155
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+/// 功能：创建以当前进程为首的新会话和新进程组。
+///
+/// 返回值：成功返回新会话号，当前进程已是组长时返回负的错误码。
+///
+/// syscall ID：157
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+/// 功能：读取或清空内核日志缓冲区。
+///
+/// 参数：`action` 为操作类型（同 Linux syslog(2)），读取时 `buf` 给出缓冲区。
+/// 读取只返回放得下的最新的完整行；没有消耗日志的读取，SIZE_UNREAD 返回缓冲区中的全部字节数。
+///
+/// 返回值：读取时返回读到的字节数，查询大小时返回字节数，出错返回负的错误码。
+///
+/// syscall ID：116
+pub fn sys_syslog(action: usize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_SYSLOG, [action, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+/// 功能：按 `key` 查找共享内存段，不存在且 `flags` 含 IPC_CREAT 时创建一个 `size` 字节的段。
+///
+/// 返回值：成功返回段的 id，否则返回负的错误码。
+///
+/// syscall ID：194
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
 
-pub fn sys_fork() -> isize {
-    syscall(SYSCALL_FORK, [0, 0, 0])
+/// 功能：控制共享内存段，目前只支持 IPC_RMID 删除段。
+///
+/// syscall ID：195
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0])
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0])
+/// 功能：把共享内存段映射到当前地址空间，地址由内核选择。
+///
+/// 返回值：成功返回映射的起始地址，否则返回负的错误码。
+///
+/// syscall ID：196
+pub fn sys_shmat(id: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, 0, flags])
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+/// 功能：解除 `addr` 处的共享内存映射。
+///
+/// syscall ID：197
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
+/// 功能：解除 `addr` 处 `len` 字节的映射，只能整段解除 mmap 建立的映射。
+///
+/// 返回值：成功返回 0，否则返回负的错误码。
+///
+/// syscall ID：215
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0])
+}
+
+/// 功能：创建当前进程的副本（子进程），目前只支持 fork 的用法。
+///
+/// 参数：`flags` 的低 8 位为子进程退出时发给父进程的信号，`stack` 非 0 时为子进程的栈指针。
+///
+/// 返回值：父进程中返回子进程的 pid，子进程中返回 0，出错返回负的错误码。
+///
+/// syscall ID：220
+pub fn sys_clone(flags: usize, stack: usize) -> isize {
+    syscall6(SYSCALL_CLONE, [flags, stack, 0, 0, 0, 0])
+}
+
+/// 功能：将当前进程的地址空间替换为 `path` 对应的程序并开始执行。
+///
+/// 参数：`path`、`argv` 和 `envp` 中的字符串均以 \0 结尾，`argv` 和 `envp` 以空指针结尾。
+///
+/// 返回值：成功时不返回，否则返回负的错误码。
+///
+/// syscall ID：221
+pub fn sys_execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> isize {
+    syscall(SYSCALL_EXECVE, [path as usize, argv as usize, envp as usize])
+}
+
+/// 功能：把 `len` 字节映射到当前地址空间，匿名映射的页面清零，文件映射从 `fd` 的 `offset` 处开始。
+///
+/// 参数：`prot` 为 PROT_READ/PROT_WRITE/PROT_EXEC 的组合；`flags` 含 MAP_SHARED 或 MAP_PRIVATE 之一，
+///      私有文件映射在写入时复制页面，程序文件不可写，其共享映射只能只读；`offset` 须按页对齐。
+///
+/// 返回值：成功返回映射的起始地址，否则返回负的错误码。
+///
+/// syscall ID：222
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [addr, len, prot, flags, fd as usize, offset])
+}
+
+/// 功能：等待子进程退出并回收其资源。
+///
+/// 参数：`pid` 为 -1 时等待任意子进程，为 0 时等待同组的子进程，小于 -1 时等待进程组 -`pid` 中的子进程；`wstatus` 保存退出状态，退出码位于 8..16 位；
+///      `options` 含 WNOHANG 时不等待。
+///
+/// 返回值：返回被回收子进程的 pid，WNOHANG 下没有退出的子进程时返回 0，出错返回负的错误码。
+///
+/// syscall ID：260
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize) -> isize {
+    syscall6(SYSCALL_WAIT4, [pid as usize, wstatus as usize, options, 0, 0, 0])
+}
+
+/// 功能：获取物理页帧、内核堆、slab 和交换区的使用情况。
+///
+/// 参数：`info` 指向用于保存结果的 `MemInfo`。
+///
+/// 返回值：总是返回 0。
+///
+/// syscall ID：1001（非 Linux 标准）
+pub fn sys_meminfo(info: *mut MemInfo) -> isize {
+    syscall(SYSCALL_MEMINFO, [info as usize, 0, 0])
+}
+
+/// 功能：获取可以 exec 的程序名列表，每个名字以 \0 结尾。
+///
+/// 参数：`buf` 为缓冲区，放不下的部分被截断。
+///
+/// 返回值：返回整个列表的字节数。
+///
+/// syscall ID：1002（非 Linux 标准）
+pub fn sys_app_names(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_APP_NAMES, [buf.as_mut_ptr() as usize, buf.len(), 0])
 }
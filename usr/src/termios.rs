@@ -0,0 +1,32 @@
+//! Settings of the console as a terminal, only ECHO has an effect
+
+use crate::errno::{decode, Errno};
+use crate::syscall::sys_ioctl;
+
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+
+/// `lflag` bit, input is echoed by the kernel
+pub const ECHO: u32 = 0o10;
+pub const NCCS: usize = 19;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+pub fn tcgetattr(fd: usize) -> Result<Termios, Errno> {
+    let mut termios = Termios::default();
+    decode(sys_ioctl(fd, TCGETS, &mut termios as *mut _ as usize))?;
+    Ok(termios)
+}
+
+pub fn tcsetattr(fd: usize, termios: &Termios) -> Result<(), Errno> {
+    decode(sys_ioctl(fd, TCSETS, termios as *const _ as usize)).map(|_| ())
+}